@@ -1,6 +1,9 @@
-use crate::renderer::{ArgbColour, Rect};
-use crate::measurer::{MeasuredLength, MeasuredElement};
-use crate::layout::LayoutDirection;
+use crate::renderer::{ArgbColour, DrawCommand, Rect};
+use crate::measurer::{MeasuredLength, MeasuredElement, clamp};
+use crate::grid::{Grid, Track};
+use crate::layout::{
+    Alignment, Axis, Justification, LayoutDirection, LayoutError, Overflow, OverflowDiagnostic, Visibility,
+};
 
 #[derive(Debug, PartialEq)]
 pub struct ArrangedElement {
@@ -9,19 +12,104 @@ pub struct ArrangedElement {
     pub(crate) y: f32,
     pub width: f32,
     pub height: f32,
+    /// Whether the element and its descendants are left out of the flattened rects, though they still take up space
+    pub(crate) hidden: bool,
+    /// Where this element's children don't fit it
+    pub(crate) overflow: Vec<OverflowDiagnostic>,
+    /// Whether children are clipped to this element's rect and whether it scrolls
+    pub(crate) overflow_mode: Overflow,
+    /// How far a scroll container's children are scrolled, after keeping it within the scrollable range
+    pub(crate) scroll_offset: f32,
+    /// Whether the scroll offset is measured back from the end edge, as children of reversed directions are
+    /// anchored there
+    pub(crate) scrolls_from_end: bool,
     pub(crate) children: Vec<ArrangedElement>,
 }
 
 impl ArrangedElement {
     pub(crate) fn flatten(&self) -> Vec<Rect> {
-        let mut elements = vec![self.to_rect()];
+        self.flatten_clipped(None)
+    }
+
+    /// Flattens the tree, cutting rects down to the clip rect of their clipping ancestors and leaving out those that
+    /// are clipped away entirely
+    fn flatten_clipped(&self, clip: Option<&Rect>) -> Vec<Rect> {
+        if self.hidden {
+            return vec![];
+        }
+        let rect = match clip {
+            Some(clip) => intersect(&self.to_rect(), clip),
+            None => self.to_rect(),
+        };
+        let mut elements = if clip.is_none() || (rect.width > 0.0 && rect.height > 0.0) { vec![rect] } else { vec![] };
+        let child_clip = match self.overflow_mode {
+            Overflow::Visible => clip.copied(),
+            Overflow::Clip | Overflow::Scroll => Some(rect),
+        };
         for child in self.children.iter() {
-            let x = &mut child.flatten();
+            let x = &mut child.flatten_clipped(child_clip.as_ref());
             elements.append(x)
         }
         elements
     }
 
+    /// Lists the commands to draw the tree, leaving the renderer to clip the children of clipping elements to their
+    /// rect rather than cutting the rects down
+    pub(crate) fn display_list(&self) -> Vec<DrawCommand> {
+        if self.hidden {
+            return vec![];
+        }
+        let mut commands = vec![DrawCommand::Rect(self.to_rect())];
+        let clips = self.overflow_mode != Overflow::Visible && !self.children.is_empty();
+        if clips {
+            commands.push(DrawCommand::PushClip { x: self.x, y: self.y, width: self.width, height: self.height });
+        }
+        for child in self.children.iter() {
+            commands.append(&mut child.display_list());
+        }
+        if clips {
+            commands.push(DrawCommand::PopClip);
+        }
+        commands
+    }
+
+    /// Finds the innermost visible scroll container under a point
+    pub(crate) fn scroll_container_at(&self, x: f32, y: f32) -> Option<&ArrangedElement> {
+        if self.hidden {
+            return None;
+        }
+        let contains = x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height;
+        if self.overflow_mode != Overflow::Visible && !contains {
+            return None;
+        }
+        self.children.iter().rev()
+            .find_map(|child| child.scroll_container_at(x, y))
+            .or(if self.overflow_mode == Overflow::Scroll { Some(self) } else { None })
+    }
+
+    /// Rounds the edges of the element and its descendants to device pixels. Edges are rounded rather than sizes, so
+    /// elements that touched still do and a container's size changes by less than a pixel.
+    pub(crate) fn snap_to_pixels(&mut self, scale_factor: f32) {
+        let snap = |dp: f32| (dp * scale_factor).round() / scale_factor;
+        let (left, top) = (snap(self.x), snap(self.y));
+        self.width = snap(self.x + self.width) - left;
+        self.height = snap(self.y + self.height) - top;
+        self.x = left;
+        self.y = top;
+        for child in self.children.iter_mut() {
+            child.snap_to_pixels(scale_factor);
+        }
+    }
+
+    /// Collects the overflow of this element and its descendants, in document order
+    pub(crate) fn overflow_diagnostics(&self) -> Vec<OverflowDiagnostic> {
+        let mut diagnostics = self.overflow.clone();
+        for child in self.children.iter() {
+            diagnostics.append(&mut child.overflow_diagnostics());
+        }
+        diagnostics
+    }
+
     fn to_rect(&self) -> Rect {
         let colours = vec![
             ArgbColour::from_hex("#F2DC5D").unwrap(),
@@ -44,47 +132,559 @@ impl ArrangedElement {
     }
 }
 
-pub fn arrange_layout_tree(elem: &MeasuredElement, x: f32, y: f32, width: f32, height: f32) -> ArrangedElement {
-    let content_primary_measure = elem.children.iter()
-        .fold(CombinedMeasuredLength::zero(),
-              |acc, child| acc.combine(&child.primary_measure(&elem.layout_direction)));
-    let star_unit_value = (match elem.layout_direction {
-        LayoutDirection::Row => height,
-        LayoutDirection::Column => width,
-    } - content_primary_measure.dp) / content_primary_measure.star;
-    let is_row = match elem.layout_direction {
-        LayoutDirection::Row => true,
-        LayoutDirection::Column => false,
+pub fn arrange_layout_tree(elem: &MeasuredElement, x: f32, y: f32, width: f32, height: f32) -> Result<ArrangedElement, LayoutError> {
+    let inner = Inner {
+        x: x + elem.padding.left,
+        y: y + elem.padding.top,
+        width: (width - elem.padding.horizontal()).max(0.0),
+        height: (height - elem.padding.vertical()).max(0.0),
     };
-    let mut primary_offset = if is_row { y } else { x };
+    let mut overflow = vec![];
+    let (flowed_children, scroll_offset) = if let Some(grid) = &elem.grid {
+        (arrange_grid(elem, grid, &inner, &mut overflow)?, 0.0)
+    } else if elem.layout_direction.is_stack() {
+        (arrange_stack(elem, &inner, &mut overflow)?, 0.0)
+    } else {
+        arrange_flow(elem, &inner, &mut overflow)?
+    };
+    // The other children come back in document order, which is paint order, so each absolute child goes in between
+    // them at its own index
+    let mut flowed_children = flowed_children.into_iter();
     let mut arranged_children = vec![];
     for child in elem.children.iter() {
-        let primary_measure = child.primary_measure(&elem.layout_direction)
-            .apply_star_value(star_unit_value);
-        let secondary_measure = match child.secondary_measure(&elem.layout_direction) {
-            MeasuredLength::Dp(dp) => dp,
-            MeasuredLength::Star(_) => panic!("Star not supported on secondary measure"),
-            MeasuredLength::Fill => width,
-        };
-        arranged_children.push(arrange_layout_tree(
-            child,
-            if is_row { x } else { x + primary_offset },
-            if is_row { y + primary_offset } else { y },
-            if is_row { secondary_measure } else { primary_measure },
-            if is_row { primary_measure } else { secondary_measure }));
-        primary_offset += primary_measure;
+        if child.is_absolute() {
+            arranged_children.push(arrange_absolute(child, x, y, width, height)?);
+        } else {
+            arranged_children.extend(flowed_children.next());
+        }
     }
 
-    ArrangedElement {
+    Ok(ArrangedElement {
         number: elem.number,
         x,
         y,
         width,
         height,
+        hidden: elem.visibility == Visibility::Hidden,
+        overflow,
+        overflow_mode: elem.overflow,
+        scroll_offset,
+        scrolls_from_end: elem.layout_direction.is_reverse(),
         children: arranged_children,
+    })
+}
+
+/// Extends a scroll container's inner area along its primary axis to fit its content, moved back by the scroll
+/// offset. Returns the area along with the offset, kept between 0 and the length of content out of view. Reversed
+/// children are anchored to the end edge, so their offset is measured back from there.
+fn scroll_inner(elem: &MeasuredElement, inner: &Inner) -> (Inner, f32) {
+    let direction = &elem.layout_direction;
+    let visible_size = inner.primary_size(direction);
+    let content_size = if direction.is_row() { elem.content_height } else { elem.content_width }
+        - elem.padding.primary(direction);
+    let scroll_range = (content_size - visible_size).max(0.0);
+    let offset = clamp(elem.scroll_offset, 0.0, scroll_range);
+    let shift = if direction.is_reverse() { scroll_range - offset } else { offset };
+    let inner = if direction.is_row() {
+        Inner { y: inner.y - shift, height: visible_size + scroll_range, ..*inner }
+    } else {
+        Inner { x: inner.x - shift, width: visible_size + scroll_range, ..*inner }
+    };
+    (inner, offset)
+}
+
+/// Extends a wrapping scroll container's inner area along its secondary axis, where its lines stack up, to fit the
+/// lines, moved back by the scroll offset. Lines are broken against the visible area first, so scrolling doesn't
+/// stop them wrapping. Returns the area along with the offset, kept between 0 and the length of lines out of view.
+fn scroll_lines(elem: &MeasuredElement, inner: &Inner, lines: &[Line]) -> (Inner, f32) {
+    let direction = &elem.layout_direction;
+    let visible_size = inner.secondary_size(direction);
+    let content_size = lines.last().map_or(0.0, |line| line.offset + line.size);
+    let scroll_range = (content_size - visible_size).max(0.0);
+    let offset = clamp(elem.scroll_offset, 0.0, scroll_range);
+    let inner = if direction.is_row() {
+        Inner { x: inner.x - offset, width: visible_size + scroll_range, ..*inner }
+    } else {
+        Inner { y: inner.y - offset, height: visible_size + scroll_range, ..*inner }
+    };
+    (inner, offset)
+}
+
+/// The overlap of two rects, with no width or height if they don't overlap
+fn intersect(rect: &Rect, clip: &Rect) -> Rect {
+    let left = rect.x.max(clip.x);
+    let top = rect.y.max(clip.y);
+    let right = (rect.x + rect.width).min(clip.x + clip.width);
+    let bottom = (rect.y + rect.height).min(clip.y + clip.height);
+    Rect { x: left, y: top, width: (right - left).max(0.0), height: (bottom - top).max(0.0), ..*rect }
+}
+
+/// Below this, children are taken to fit exactly, allowing for rounding errors
+const OVERFLOW_TOLERANCE: f32 = 0.01;
+
+/// Records how far a container's children extend past its inner size along an axis, or how much space they leave
+/// unfilled when negative
+fn report_overflow(overflow: &mut Vec<OverflowDiagnostic>, elem: &MeasuredElement, axis: Axis, amount: f32) {
+    if amount.abs() > OVERFLOW_TOLERANCE {
+        overflow.push(OverflowDiagnostic { number: elem.number, axis, amount });
+    }
+}
+
+/// The area inside a container's padding
+#[derive(Copy, Clone)]
+struct Inner {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Inner {
+    fn primary_size(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.height } else { self.width }
+    }
+
+    fn secondary_size(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.width } else { self.height }
+    }
+}
+
+/// Arranges a row or column's children along its primary axis, in lines if it wraps. Space left along the primary
+/// axis is reported as underflow unless the children wrap, since lines not filling it is how wrapping works. Returns
+/// the children along with the scroll offset of a scroll container.
+fn arrange_flow(elem: &MeasuredElement, inner: &Inner, overflow: &mut Vec<OverflowDiagnostic>)
+                -> Result<(Vec<ArrangedElement>, f32), LayoutError> {
+    let direction = &elem.layout_direction;
+    let scrolls = elem.overflow == Overflow::Scroll;
+    let (inner, primary_scroll_offset) = if scrolls && !direction.is_wrap() {
+        scroll_inner(elem, inner)
+    } else {
+        (*inner, 0.0)
+    };
+    let inner = &inner;
+    let secondary_size = inner.secondary_size(direction);
+    // A primary size derived from an aspect ratio takes part in star distribution as a dp size. Wrapping lines
+    // aren't known yet, so fill secondary sizes are taken against the whole container here.
+    let primary_measures: Vec<MeasuredLength> = elem.children.iter()
+        .map(|child| if child.derives_primary(direction) {
+            let (_, secondary) = align_on_axis(
+                child, child.secondary_measure(direction), direction.secondary_attribute(),
+                child.align_self.unwrap_or(elem.align), secondary_size - child.margin.secondary(direction),
+                secondary_size, child.constraints.secondary_limits(direction, secondary_size))?;
+            Ok(MeasuredLength::Dp(child.derive_size(secondary)))
+        } else {
+            Ok(child.primary_measure(direction).resolve_percent(inner.primary_size(direction)))
+        })
+        .collect::<Result<_, LayoutError>>()?;
+    let flow_children: Vec<usize> = (0..elem.children.len()).filter(|&i| !elem.children[i].is_absolute()).collect();
+    if let Some(child) = flow_children.iter()
+        .find(|&&i| CombinedMeasuredLength::zero().combine(&primary_measures[i]).is_none())
+        .map(|&i| &elem.children[i]) {
+        return Err(LayoutError::FillOnPrimaryMeasure {
+            number: child.number,
+            node_name: child.node_name.clone(),
+            attribute: String::from(direction.primary_attribute()),
+        });
+    }
+    let lines = if direction.is_wrap() {
+        break_lines(elem, &flow_children, &primary_measures, inner)
+    } else {
+        vec![Line { children: flow_children, offset: 0.0, size: secondary_size }]
+    };
+    let (inner, scroll_offset) = if scrolls && direction.is_wrap() {
+        scroll_lines(elem, inner, &lines)
+    } else {
+        (*inner, primary_scroll_offset)
+    };
+    let inner = &inner;
+    let mut arranged_children = vec![];
+    let (mut primary_excess, mut secondary_excess) = (f32::NEG_INFINITY, 0.0f32);
+    for line in lines.iter() {
+        let mut arranged_line = arrange_line(elem, line, &primary_measures, inner)?;
+        primary_excess = primary_excess.max(arranged_line.primary_excess);
+        secondary_excess = secondary_excess.max(arranged_line.secondary_excess);
+        arranged_children.append(&mut arranged_line.children);
+    }
+    if lines.iter().any(|line| !line.children.is_empty()) {
+        report_overflow(overflow, elem, direction.primary_axis(),
+                        if direction.is_wrap() { primary_excess.max(0.0) } else { primary_excess });
+        report_overflow(overflow, elem, direction.secondary_axis(), secondary_excess);
+    }
+    Ok((arranged_children, scroll_offset))
+}
+
+/// Arranges a stack's children over the whole inner rect, each sized and aligned on both axes independently of its
+/// siblings. Later children are drawn over earlier ones since flattening emits them in document order.
+fn arrange_stack(elem: &MeasuredElement, inner: &Inner, overflow: &mut Vec<OverflowDiagnostic>)
+                 -> Result<Vec<ArrangedElement>, LayoutError> {
+    let mut arranged_children = vec![];
+    let (mut width_excess, mut height_excess) = (0.0f32, 0.0f32);
+    for child in elem.children.iter().filter(|child| !child.is_absolute()) {
+        let alignment = child.align_self.unwrap_or(elem.align);
+        let (x_offset, y_offset, width, height) =
+            align_in_area(child, alignment, (inner.width, inner.height), (inner.width, inner.height))?;
+        width_excess = width_excess.max(width + child.margin.horizontal() - inner.width);
+        height_excess = height_excess.max(height + child.margin.vertical() - inner.height);
+        arranged_children.push(arrange_layout_tree(child, inner.x + x_offset, inner.y + y_offset, width, height)?);
+    }
+    report_overflow(overflow, elem, Axis::Horizontal, width_excess);
+    report_overflow(overflow, elem, Axis::Vertical, height_excess);
+    Ok(arranged_children)
+}
+
+/// Arranges a grid's children in their areas, each sized and aligned on both axes within its area as in a stack.
+/// Tracks share the grid's size like children of a row or column, with `gap` between both columns and rows.
+fn arrange_grid(elem: &MeasuredElement, grid: &Grid, inner: &Inner, overflow: &mut Vec<OverflowDiagnostic>)
+                -> Result<Vec<ArrangedElement>, LayoutError> {
+    let columns = resolve_tracks(&grid.columns, inner.width, elem.gap);
+    let rows = resolve_tracks(&grid.rows, inner.height, elem.gap);
+    let tracks_excess = |tracks: &[(f32, f32)], size: f32| {
+        tracks.last().map(|(offset, track_size)| offset + track_size - size).unwrap_or(0.0).max(0.0)
+    };
+    report_overflow(overflow, elem, Axis::Horizontal, tracks_excess(&columns, inner.width));
+    report_overflow(overflow, elem, Axis::Vertical, tracks_excess(&rows, inner.height));
+    let span = |tracks: &[(f32, f32)], start: usize, count: usize| {
+        let (first_offset, _) = tracks[start];
+        let (last_offset, last_size) = tracks[start + count - 1];
+        (first_offset, last_offset + last_size - first_offset)
+    };
+    let mut arranged_children = vec![];
+    for (child, area) in elem.children.iter().filter(|child| !child.is_absolute()).zip(grid.areas.iter()) {
+        let (area_x, area_width) = span(&columns, area.column, area.column_span);
+        let (area_y, area_height) = span(&rows, area.row, area.row_span);
+        let alignment = child.align_self.unwrap_or(elem.align);
+        let (x_offset, y_offset, width, height) =
+            align_in_area(child, alignment, (area_width, area_height), (area_width, area_height))?;
+        arranged_children.push(arrange_layout_tree(child, inner.x + area_x + x_offset, inner.y + area_y + y_offset,
+                                                    width, height)?);
+    }
+    Ok(arranged_children)
+}
+
+/// Returns the offset and size of each track. Star tracks share the space left by the others, but as with star
+/// children, don't shrink below the content of the children in them.
+fn resolve_tracks(tracks: &[Track], size: f32, gap: f32) -> Vec<(f32, f32)> {
+    let measures: Vec<MeasuredLength> = tracks.iter().map(|track| track.length.resolve_percent(size)).collect();
+    let limits: Vec<(f32, f32)> = tracks.iter()
+        .map(|track| match track.length {
+            MeasuredLength::Star(_) => (track.content, f32::INFINITY),
+            _ => (0.0, f32::INFINITY),
+        })
+        .collect();
+    let total_gap = gap * tracks.len().saturating_sub(1) as f32;
+    let mut offset = 0.0;
+    resolve_primary_sizes(&measures, &limits, size - total_gap).into_iter()
+        .map(|track_size| {
+            let track = (offset, track_size);
+            offset += track_size + gap;
+            track
+        })
+        .collect()
+}
+
+/// Arranges an absolutely positioned child against its parent's rect. Along each axis, the child is placed at its
+/// start offset, or failing that its end offset, and fill sizes stretch between the two.
+fn arrange_absolute(child: &MeasuredElement, x: f32, y: f32, width: f32, height: f32)
+                    -> Result<ArrangedElement, LayoutError> {
+    let (offsets, margin) = (&child.offsets, &child.margin);
+    let position_x = |measure| position_on_axis(measure, offsets.left, offsets.right, margin.left, margin.right,
+                                                width, child.constraints.width_limits(width));
+    let position_y = |measure| position_on_axis(measure, offsets.top, offsets.bottom, margin.top, margin.bottom,
+                                                height, child.constraints.height_limits(height));
+    let ((x_offset, child_width), (y_offset, child_height)) = if child.aspect_ratio.is_none() {
+        (position_x(child.width), position_y(child.height))
+    } else if child.derives_width() {
+        let (y_offset, child_height) = position_y(child.height);
+        (position_x(MeasuredLength::Dp(child.derive_size(child_height))), (y_offset, child_height))
+    } else {
+        let (x_offset, child_width) = position_x(child.width);
+        ((x_offset, child_width), position_y(MeasuredLength::Dp(child.derive_size(child_width))))
+    };
+    arrange_layout_tree(child, x + x_offset, y + y_offset, child_width, child_height)
+}
+
+/// Returns the offset and size of an absolutely positioned child along one axis
+fn position_on_axis(measure: MeasuredLength, start: Option<f32>, end: Option<f32>, margin_start: f32, margin_end: f32,
+                    parent_size: f32, (min, max): (f32, f32)) -> (f32, f32) {
+    let available = parent_size - start.unwrap_or(0.0) - end.unwrap_or(0.0) - margin_start - margin_end;
+    let size = clamp(match measure {
+        MeasuredLength::Dp(dp) => dp,
+        MeasuredLength::Percent(percent) => parent_size * percent / 100.0,
+        MeasuredLength::Star(_) | MeasuredLength::Fill => available,
+    }, min, max);
+    let offset = match (start, end) {
+        (None, Some(end)) => parent_size - end - margin_end - size,
+        (start, _) => start.unwrap_or(0.0) + margin_start,
+    };
+    (offset, size)
+}
+
+/// Sizes and aligns a child on both axes within an area it doesn't share with its siblings, returning its offsets
+/// within the area and its size. Percentages resolve against the parent size. With an aspect ratio, the derived axis is
+/// sized from the other one rather than stretched.
+fn align_in_area(child: &MeasuredElement, alignment: Alignment, (area_width, area_height): (f32, f32),
+                 (parent_width, parent_height): (f32, f32)) -> Result<(f32, f32, f32, f32), LayoutError> {
+    let margin = &child.margin;
+    let align_x = |measure, alignment| align_on_axis(child, measure, "width", alignment,
+                                                     area_width - margin.horizontal(), parent_width,
+                                                     child.constraints.width_limits(parent_width));
+    let align_y = |measure, alignment| align_on_axis(child, measure, "height", alignment,
+                                                     area_height - margin.vertical(), parent_height,
+                                                     child.constraints.height_limits(parent_height));
+    let ((x_offset, width), (y_offset, height)) = if child.aspect_ratio.is_none() {
+        (align_x(child.width, alignment)?, align_y(child.height, alignment)?)
+    } else if child.derives_width() {
+        let (y_offset, height) = align_y(child.height, alignment)?;
+        (align_x(MeasuredLength::Dp(child.derive_size(height)), without_stretch(alignment))?, (y_offset, height))
+    } else {
+        let (x_offset, width) = align_x(child.width, alignment)?;
+        ((x_offset, width), align_y(MeasuredLength::Dp(child.derive_size(width)), without_stretch(alignment))?)
+    };
+    Ok((margin.left + x_offset, margin.top + y_offset, width, height))
+}
+
+/// Alignment for a size derived from an aspect ratio, which stretching would override
+fn without_stretch(alignment: Alignment) -> Alignment {
+    match alignment {
+        Alignment::Stretch => Alignment::Start,
+        alignment => alignment,
+    }
+}
+
+/// Sizes a child along an axis where it doesn't share space with its siblings and aligns it within the available
+/// space, returning its offset and size. Percentages resolve against the parent's full inner size.
+fn align_on_axis(child: &MeasuredElement, measure: MeasuredLength, attribute: &str, alignment: Alignment,
+                 available: f32, parent_size: f32, (min, max): (f32, f32)) -> Result<(f32, f32), LayoutError> {
+    let size = match (alignment, measure) {
+        (_, MeasuredLength::Star(_)) => return Err(LayoutError::StarOnSecondaryMeasure {
+            number: child.number,
+            node_name: child.node_name.clone(),
+            attribute: String::from(attribute),
+        }),
+        (Alignment::Stretch, _) | (_, MeasuredLength::Fill) => available,
+        (_, MeasuredLength::Dp(dp)) => dp,
+        (_, MeasuredLength::Percent(percent)) => parent_size * percent / 100.0,
+    };
+    let size = clamp(size, min, max);
+    let offset = match alignment {
+        Alignment::Start | Alignment::Stretch => 0.0,
+        Alignment::Center => (available - size) / 2.0,
+        Alignment::End => available - size,
+    };
+    Ok((offset, size))
+}
+
+/// A run of children arranged along the primary axis. A container that doesn't wrap has a single line spanning its
+/// secondary size.
+struct Line {
+    /// Indices of the line's children
+    children: Vec<usize>,
+    /// Distance of the line from the start of the secondary axis
+    offset: f32,
+    size: f32,
+}
+
+/// Splits a wrapping container's children into lines, starting a new line whenever the next child wouldn't fit the
+/// primary size. Children are measured at their base size for this, with star children at their minimum, so star
+/// children only share the space left over in their own line. Each line is as thick as its thickest child, and the
+/// lines share out any secondary space they leave over between them, so fill children grow with the container.
+fn break_lines(elem: &MeasuredElement, flow_children: &[usize], primary_measures: &[MeasuredLength], inner: &Inner)
+               -> Vec<Line> {
+    let direction = &elem.layout_direction;
+    let primary_size = inner.primary_size(direction);
+    let mut lines: Vec<Line> = vec![];
+    let mut line = Line { children: vec![], offset: 0.0, size: 0.0 };
+    let mut line_primary = 0.0;
+    for &i in flow_children {
+        let (child, measure) = (&elem.children[i], &primary_measures[i]);
+        let (min, max) = child.primary_limits(direction, primary_size, elem.content_sized_primary);
+        let outer_primary = clamp(measure.apply_star_value(0.0).unwrap_or(0.0), min, max)
+            + child.margin.primary(direction);
+        if !line.children.is_empty() && line_primary + elem.gap + outer_primary > primary_size {
+            lines.push(line);
+            line = Line { children: vec![], offset: 0.0, size: 0.0 };
+            line_primary = 0.0;
+        }
+        if !line.children.is_empty() {
+            line_primary += elem.gap;
+        }
+        line_primary += outer_primary;
+        let outer_secondary = base_secondary_size(elem, child, inner.secondary_size(direction))
+            + child.margin.secondary(direction);
+        line.size = line.size.max(outer_secondary);
+        line.children.push(i);
+    }
+    if !line.children.is_empty() {
+        lines.push(line);
+    }
+    let used_secondary = lines.iter().map(|line| line.size).sum::<f32>()
+        + elem.line_gap * lines.len().saturating_sub(1) as f32;
+    let extra_secondary = (inner.secondary_size(direction) - used_secondary).max(0.0) / lines.len().max(1) as f32;
+    let mut offset = 0.0;
+    for line in lines.iter_mut() {
+        line.size += extra_secondary;
+        line.offset = offset;
+        offset += line.size + elem.line_gap;
+    }
+    lines
+}
+
+/// The secondary size a child takes up in a wrapping line, before stretching or filling to the line's size
+fn base_secondary_size(elem: &MeasuredElement, child: &MeasuredElement, secondary_size: f32) -> f32 {
+    let direction = &elem.layout_direction;
+    let size = match (child.align_self.unwrap_or(elem.align), child.secondary_measure(direction)) {
+        (Alignment::Stretch, _) | (_, MeasuredLength::Fill) => child.intrinsic_secondary_size(direction),
+        (_, MeasuredLength::Dp(dp)) => dp,
+        (_, MeasuredLength::Percent(percent)) => secondary_size * percent / 100.0,
+        (_, MeasuredLength::Star(_)) => 0.0,
+    };
+    let (min, max) = child.constraints.secondary_limits(direction, secondary_size);
+    clamp(size, min, max)
+}
+
+/// A line's arranged children and how far they extend past the container's inner size along each axis
+struct ArrangedLine {
+    children: Vec<ArrangedElement>,
+    /// Negative when the children leave primary space unfilled
+    primary_excess: f32,
+    secondary_excess: f32,
+}
+
+/// Arranges one line of children: sizes them along the primary axis, distributes any leftover space and aligns each
+/// child within the line's secondary size. Percentages still resolve against the container's full inner size.
+fn arrange_line(elem: &MeasuredElement, line: &Line, primary_measures: &[MeasuredLength], inner: &Inner)
+                -> Result<ArrangedLine, LayoutError> {
+    let direction = &elem.layout_direction;
+    let is_row = direction.is_row();
+    let primary_size = inner.primary_size(direction);
+    let secondary_size = inner.secondary_size(direction);
+    let children: Vec<&MeasuredElement> = line.children.iter().map(|&i| &elem.children[i]).collect();
+    let measures: Vec<MeasuredLength> = line.children.iter().map(|&i| primary_measures[i]).collect();
+    let primary_limits: Vec<(f32, f32)> = children.iter()
+        .map(|child| child.primary_limits(direction, primary_size, elem.content_sized_primary))
+        .collect();
+    let total_primary_margin: f32 = children.iter()
+        .map(|child| child.margin.primary(direction))
+        .sum();
+    let total_gap = elem.gap * children.len().saturating_sub(1) as f32;
+    let available_primary = primary_size - total_primary_margin - total_gap;
+    let primary_sizes = resolve_primary_sizes(&measures, &primary_limits, available_primary);
+    let leftover_primary = available_primary - primary_sizes.iter().sum::<f32>();
+    let (mut primary_offset, justify_spacing) = justify_offsets(elem.justify, leftover_primary, children.len());
+    let mut arranged_children = vec![];
+    let mut secondary_excess = 0.0f32;
+    for (child, primary_measure) in children.into_iter().zip(primary_sizes) {
+        let margin = &child.margin;
+        let alignment = child.align_self.unwrap_or(elem.align);
+        let (secondary_measure, alignment) = if child.derives_secondary(direction) {
+            (MeasuredLength::Dp(child.derive_size(primary_measure)), without_stretch(alignment))
+        } else {
+            (child.secondary_measure(direction), alignment)
+        };
+        let (align_offset, secondary_measure) = align_on_axis(
+            child, secondary_measure, direction.secondary_attribute(), alignment,
+            line.size - margin.secondary(direction), secondary_size,
+            child.constraints.secondary_limits(direction, secondary_size))?;
+        // Reversed containers place children from the end edge, so each child's end margin comes first
+        let (leading_margin, trailing_margin) = if direction.is_reverse() {
+            (margin.primary_end(direction), margin.primary_start(direction))
+        } else {
+            (margin.primary_start(direction), margin.primary_end(direction))
+        };
+        primary_offset += leading_margin;
+        let primary_position = if direction.is_reverse() {
+            primary_size - primary_offset - primary_measure
+        } else {
+            primary_offset
+        };
+        let secondary_offset = line.offset + margin.secondary_start(direction) + align_offset;
+        secondary_excess = secondary_excess
+            .max(line.offset + margin.secondary(direction) + secondary_measure - secondary_size);
+        arranged_children.push(arrange_layout_tree(
+            child,
+            inner.x + if is_row { secondary_offset } else { primary_position },
+            inner.y + if is_row { primary_position } else { secondary_offset },
+            if is_row { secondary_measure } else { primary_measure },
+            if is_row { primary_measure } else { secondary_measure })?);
+        primary_offset += primary_measure + trailing_margin + elem.gap + justify_spacing;
+    }
+    Ok(ArrangedLine { children: arranged_children, primary_excess: -leftover_primary, secondary_excess })
+}
+
+/// Returns the offset before the first child and the extra spacing between children needed to distribute leftover
+/// primary space. There's no leftover space to distribute when star children take it up or the children overflow.
+fn justify_offsets(justify: Justification, leftover: f32, child_count: usize) -> (f32, f32) {
+    if leftover <= 0.0 || child_count == 0 {
+        return (0.0, 0.0);
+    }
+    let count = child_count as f32;
+    match justify {
+        Justification::Start => (0.0, 0.0),
+        Justification::Center => (leftover / 2.0, 0.0),
+        Justification::End => (leftover, 0.0),
+        Justification::SpaceBetween if child_count == 1 => (0.0, 0.0),
+        Justification::SpaceBetween => (0.0, leftover / (count - 1.0)),
+        Justification::SpaceAround => (leftover / count / 2.0, leftover / count),
+        Justification::SpaceEvenly => (leftover / (count + 1.0), leftover / (count + 1.0)),
+    }
+}
+
+/// Resolves the children's primary sizes. The space left by fixed-size children is shared between star children in
+/// proportion to their weights; a star child whose share would break its min or max constraint is frozen at that
+/// limit and the space is redistributed between the remaining star children, as flexbox does. When there are no
+/// star children the star unit value is 0 rather than dividing by a zero star total.
+fn resolve_primary_sizes(measures: &[MeasuredLength], limits: &[(f32, f32)], primary_size: f32) -> Vec<f32> {
+    // Star children get nothing when there's no finite space to share out
+    let primary_size = if primary_size.is_finite() { primary_size } else { 0.0 };
+    let mut sizes: Vec<Option<f32>> = measures.iter().zip(limits.iter())
+        .map(|(measure, &(min, max))| match measure {
+            MeasuredLength::Star(_) => None,
+            measure => Some(clamp(measure.apply_star_value(0.0).unwrap_or(0.0), min, max)),
+        })
+        .collect();
+    loop {
+        let remaining = measures.iter().zip(sizes.iter())
+            .map(|(measure, size)| size.map(MeasuredLength::Dp).unwrap_or(*measure))
+            .fold(CombinedMeasuredLength::zero(), |acc, measure| acc.combine(&measure).unwrap_or(acc));
+        // Star children get nothing rather than negative sizes when fixed-size children overflow
+        let star_unit_value = if remaining.star > 0.0 {
+            (primary_size - remaining.dp).max(0.0) / remaining.star
+        } else {
+            0.0
+        };
+        let unresolved: Vec<(usize, f32, f32)> = measures.iter().zip(limits.iter()).enumerate()
+            .filter(|(i, _)| sizes[*i].is_none())
+            .map(|(i, (measure, &(min, max)))| {
+                let size = measure.apply_star_value(star_unit_value).unwrap_or(0.0);
+                (i, size, clamp(size, min, max))
+            })
+            .collect();
+        if unresolved.is_empty() {
+            break;
+        }
+        let total_violation: f32 = unresolved.iter().map(|(_, size, clamped)| clamped - size).sum();
+        let mut frozen = false;
+        // Min and max violations that cancel out still freeze at the clamped sizes, keeping every constraint
+        for &(i, size, clamped) in unresolved.iter() {
+            if total_violation == 0.0 || (total_violation > 0.0 && clamped > size)
+                || (total_violation < 0.0 && clamped < size) {
+                sizes[i] = Some(clamped);
+                frozen = true;
+            }
+        }
+        // Sizes that aren't finite make the violation NaN, which freezes nothing, so settle the rest at their clamped
+        // sizes rather than loop forever
+        if !frozen {
+            for (i, _, clamped) in unresolved {
+                sizes[i] = Some(clamped);
+            }
+            break;
+        }
     }
+    sizes.into_iter().map(|size| size.unwrap_or(0.0)).collect()
 }
 
+#[derive(Copy, Clone)]
 struct CombinedMeasuredLength {
     dp: f32,
     star: f32,
@@ -98,11 +698,13 @@ impl CombinedMeasuredLength {
         }
     }
 
-    pub fn combine(&self, len: &MeasuredLength) -> CombinedMeasuredLength {
+    /// Adds a primary measure to the total, or returns `None` for Fill which isn't allowed on the primary measure
+    /// and Percent which must be resolved against the parent first
+    pub fn combine(&self, len: &MeasuredLength) -> Option<CombinedMeasuredLength> {
         match len {
-            MeasuredLength::Dp(dp) => CombinedMeasuredLength { dp: self.dp + dp, ..*self },
-            MeasuredLength::Star(star) => CombinedMeasuredLength { star: self.star + star, ..*self },
-            MeasuredLength::Fill => panic!("Fill not allowed on primary measure")
+            MeasuredLength::Dp(dp) => Some(CombinedMeasuredLength { dp: self.dp + dp, ..*self }),
+            MeasuredLength::Star(star) => Some(CombinedMeasuredLength { star: self.star + star, ..*self }),
+            MeasuredLength::Percent(_) | MeasuredLength::Fill => None
         }
     }
     pub fn add(len_a: CombinedMeasuredLength, len_b: CombinedMeasuredLength) -> CombinedMeasuredLength {
@@ -0,0 +1,36 @@
+use std::{env, fs, process};
+
+use face::Severity;
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("Usage: face_lint <file>...");
+        process::exit(2);
+    }
+    let mut has_errors = false;
+    for path in paths.iter() {
+        let diagnostics = match fs::read_to_string(path) {
+            Ok(document) => match face::parse_document(&document) {
+                Ok(root) => face::validate(&root),
+                Err(err) => {
+                    println!("{}: error: {}", path, err);
+                    has_errors = true;
+                    continue;
+                }
+            },
+            Err(err) => {
+                println!("{}: error: {}", path, err);
+                has_errors = true;
+                continue;
+            }
+        };
+        for diagnostic in diagnostics.iter() {
+            println!("{}: {}", path, diagnostic);
+            has_errors |= diagnostic.severity == Severity::Error;
+        }
+    }
+    if has_errors {
+        process::exit(1);
+    }
+}
@@ -2,7 +2,7 @@ use std::convert::TryInto;
 
 use gl::types::*;
 use glutin::{ContextBuilder, PossiblyCurrent};
-use glutin::event::{Event, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent};
+use glutin::event::{Event, KeyboardInput, ModifiersState, MouseScrollDelta, VirtualKeyCode, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::GlProfile;
 use glutin::window::WindowBuilder;
@@ -13,6 +13,9 @@ use skia_safe::gpu::gl::FramebufferInfo;
 
 use crate::{layout, renderer, markup};
 
+/// How far one notch of a mouse wheel scrolls, in dp
+const SCROLL_LINE_DP: f32 = 40.0;
+
 pub fn run_face_window(root_elem: markup::MarkupElement) -> () {
     let el = EventLoop::new();
     let wb = WindowBuilder::new().with_title("Face Demo");
@@ -47,6 +50,8 @@ pub fn run_face_window(root_elem: markup::MarkupElement) -> () {
         )));
 
     let mut surface: skia_safe::Surface = skia_safe::Surface::new_null((800, 600)).unwrap();
+    let mut layout_options = layout::LayoutOptions::default();
+    let mut cursor_position = (0.0, 0.0);
     el.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
         // println!("{:?}", event);
@@ -63,6 +68,27 @@ pub fn run_face_window(root_elem: markup::MarkupElement) -> () {
                     surface = create_surface(&window_context, &fb_info, &mut gr_context);
                 }
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::CursorMoved { position, .. } => {
+                    let position = position.to_logical::<f32>(window_context.window().scale_factor());
+                    cursor_position = (position.x, position.y);
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let scale_factor = window_context.window().scale_factor();
+                    let scroll_dp = match delta {
+                        MouseScrollDelta::LineDelta(_, lines) => lines * SCROLL_LINE_DP,
+                        MouseScrollDelta::PixelDelta(position) => position.to_logical::<f32>(scale_factor).y,
+                    };
+                    let size = window_context.window().inner_size().to_logical::<f32>(scale_factor);
+                    let tree = layout::layout_tree(&root_elem, size.width, size.height, &layout_options);
+                    if let Some(container) = tree.as_ref().ok()
+                        .and_then(|tree| tree.scroll_container_at(cursor_position.0, cursor_position.1)) {
+                        // Wheel deltas are positive when scrolling up, which moves back towards the start, except in
+                        // reversed containers, whose offsets grow away from the end edge they're anchored to
+                        let scroll_dp = if container.scrolls_from_end { -scroll_dp } else { scroll_dp };
+                        layout_options.scroll_offsets.insert(container.number, container.scroll_offset - scroll_dp);
+                        window_context.window().request_redraw();
+                    }
+                }
                 #[allow(deprecated)]
                 WindowEvent::KeyboardInput {
                     input: KeyboardInput {
@@ -87,12 +113,18 @@ pub fn run_face_window(root_elem: markup::MarkupElement) -> () {
                     canvas.clear(Color::GRAY);
                     let image_info = canvas.image_info();
                     let scale_factor = window_context.window().scale_factor() as f32;
-                    let rects = layout::generate_layout(
+                    layout_options.pixel_snapping = Some(scale_factor);
+                    let commands = layout::generate_display_list(
                         &root_elem,
                         image_info.width() as f32 / scale_factor,
-                        image_info.height() as f32 / scale_factor);
-                    let rects = rects.iter().map(|rect| rect.scale(scale_factor)).collect();
-                    renderer::draw_ui(canvas, rects);
+                        image_info.height() as f32 / scale_factor,
+                        &layout_options)
+                        .unwrap_or_else(|err| {
+                            eprintln!("Layout failed: {}", err);
+                            vec![]
+                        });
+                    let commands = commands.iter().map(|command| command.scale(scale_factor)).collect();
+                    renderer::draw_display_list(canvas, commands);
                 }
                 surface.canvas().flush();
                 window_context.swap_buffers().unwrap();
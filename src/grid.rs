@@ -0,0 +1,170 @@
+use crate::layout::{GridPlacement, LayoutError};
+use crate::markup::{MarkupElement, MarkupLength};
+use crate::measurer::{MeasuredElement, MeasuredLength};
+
+/// How many rows or columns a child can be placed into, which keeps a document from allocating tracks and cells
+/// without bound
+pub(crate) const MAX_GRID_TRACKS: usize = 1000;
+
+/// A grid container's tracks and the area each child occupies, indexed like its children that aren't absolutely
+/// positioned
+#[derive(PartialEq, Debug)]
+pub struct Grid {
+    pub(crate) columns: Vec<Track>,
+    pub(crate) rows: Vec<Track>,
+    pub(crate) areas: Vec<GridArea>,
+}
+
+/// A column or row of a grid
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Track {
+    /// Dp, Star or Percent; content tracks are measured to Dp
+    pub(crate) length: MeasuredLength,
+    /// The largest intrinsic size of the children that sit in this track alone
+    pub(crate) content: f32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GridArea {
+    pub(crate) row: usize,
+    pub(crate) column: usize,
+    pub(crate) row_span: usize,
+    pub(crate) column_span: usize,
+}
+
+impl Grid {
+    pub(crate) fn content_width(&self, gap: f32) -> f32 {
+        Grid::content_size(&self.columns, gap)
+    }
+
+    pub(crate) fn content_height(&self, gap: f32) -> f32 {
+        Grid::content_size(&self.rows, gap)
+    }
+
+    fn content_size(tracks: &[Track], gap: f32) -> f32 {
+        tracks.iter()
+            .map(|track| match track.length {
+                MeasuredLength::Dp(dp) => dp,
+                _ => track.content,
+            })
+            .sum::<f32>() + gap * tracks.len().saturating_sub(1) as f32
+    }
+}
+
+/// Places a grid element's children and measures its tracks. Without `columns` the grid has a single `1*` column, and
+/// rows beyond those listed in `rows` are content-sized. Children spanning several tracks don't contribute to the
+/// content size of any of them, and absolutely positioned children aren't placed at all.
+pub(crate) fn measure_grid(elem: &MarkupElement, children: &[MeasuredElement]) -> Result<Grid, LayoutError> {
+    let mut column_lengths = elem.get_columns()?;
+    if column_lengths.is_empty() {
+        column_lengths.push(MarkupLength::Star(1.0));
+    }
+    let children: Vec<&MeasuredElement> = children.iter().filter(|child| !child.is_absolute()).collect();
+    if let Some(child) = children.iter()
+        .find(|child| child.grid_placement.column.is_some_and(|column| column >= column_lengths.len())) {
+        return Err(LayoutError::BadAttributeValue {
+            number: child.number,
+            node_name: child.node_name.clone(),
+            attribute: String::from("grid-column"),
+            value: child.grid_placement.column.unwrap_or(0).to_string(),
+        });
+    }
+    let placements: Vec<GridPlacement> = children.iter().map(|child| child.grid_placement).collect();
+    let areas = place_children(&placements, column_lengths.len());
+    let mut row_lengths = elem.get_rows()?;
+    let row_count = areas.iter().map(|area| area.row + area.row_span).max().unwrap_or(0);
+    while row_lengths.len() < row_count {
+        row_lengths.push(MarkupLength::Content);
+    }
+
+    let columns = measure_tracks(&column_lengths, children.iter().zip(areas.iter())
+        .filter(|(_, area)| area.column_span == 1)
+        .map(|(child, area)| (area.column, child.intrinsic_width() + child.margin.horizontal())));
+    let rows = measure_tracks(&row_lengths, children.iter().zip(areas.iter())
+        .filter(|(_, area)| area.row_span == 1)
+        .map(|(child, area)| (area.row, child.intrinsic_height() + child.margin.vertical())));
+    Ok(Grid { columns, rows, areas })
+}
+
+/// Measures tracks given the (track index, size) of each child sitting in a single track
+fn measure_tracks(lengths: &[MarkupLength], child_sizes: impl Iterator<Item = (usize, f32)>) -> Vec<Track> {
+    let mut content = vec![0.0f32; lengths.len()];
+    for (index, size) in child_sizes {
+        content[index] = content[index].max(size);
+    }
+    lengths.iter().zip(content)
+        .map(|(length, content)| Track {
+            length: match length {
+                MarkupLength::Dp(dp) => MeasuredLength::Dp(*dp),
+                MarkupLength::Star(star) => MeasuredLength::Star(*star),
+                MarkupLength::Percent(percent) => MeasuredLength::Percent(*percent),
+                MarkupLength::Content | MarkupLength::Fill => MeasuredLength::Dp(content),
+            },
+            content,
+        })
+        .collect()
+}
+
+/// Assigns each child an area in document order. Children with both a row and a column go exactly there, even over
+/// other children; the rest take the first free cells that fit them, scanning row by row. Children without either
+/// continue from the last auto-placed child, so they fill the grid in order. Explicit columns must be within the grid,
+/// and spans wider than the columns from there to the end of the grid are narrowed to fit.
+pub(crate) fn place_children(placements: &[GridPlacement], column_count: usize) -> Vec<GridArea> {
+    let mut occupancy = Occupancy { cells: vec![], column_count };
+    let mut cursor = (0, 0);
+    let mut areas = vec![];
+    for placement in placements {
+        let row_span = placement.row_span;
+        let column_span = placement.column_span.min(column_count - placement.column.unwrap_or(0));
+        let last_column = column_count - column_span;
+        let (row, column) = match (placement.row, placement.column) {
+            (Some(row), Some(column)) => (row, column),
+            (Some(row), None) => (row, (0..=last_column)
+                .find(|&column| occupancy.fits(row, column, row_span, column_span))
+                .unwrap_or(0)),
+            (None, Some(column)) => {
+                ((0..).find(|&row| occupancy.fits(row, column, row_span, column_span)).unwrap_or(0), column)
+            }
+            (None, None) => {
+                let (row, column) = (cursor.0..)
+                    .flat_map(|row| {
+                        let first_column = if row == cursor.0 { cursor.1 } else { 0 };
+                        (first_column..=last_column).map(move |column| (row, column))
+                    })
+                    .find(|&(row, column)| occupancy.fits(row, column, row_span, column_span))
+                    .unwrap_or(cursor);
+                cursor = (row, column + column_span);
+                (row, column)
+            }
+        };
+        let area = GridArea { row, column, row_span, column_span };
+        occupancy.occupy(&area);
+        areas.push(area);
+    }
+    areas
+}
+
+/// Which cells of a grid are taken, growing by rows as children are placed
+struct Occupancy {
+    cells: Vec<Vec<bool>>,
+    column_count: usize,
+}
+
+impl Occupancy {
+    fn fits(&self, row: usize, column: usize, row_span: usize, column_span: usize) -> bool {
+        (row..row + row_span).all(|row| (column..column + column_span).all(|column| {
+            !self.cells.get(row).map(|cells| cells[column]).unwrap_or(false)
+        }))
+    }
+
+    fn occupy(&mut self, area: &GridArea) {
+        while self.cells.len() < area.row + area.row_span {
+            self.cells.push(vec![false; self.column_count]);
+        }
+        for row in area.row..area.row + area.row_span {
+            for column in area.column..area.column + area.column_span {
+                self.cells[row][column] = true;
+            }
+        }
+    }
+}
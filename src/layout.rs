@@ -1,12 +1,15 @@
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
 use regex::Regex;
 
-use renderer::Rect;
+use renderer::{DrawCommand, Rect};
 
 use crate::{arranger, measurer, renderer};
 use crate::markup::{MarkupElement, MarkupLength};
-use crate::measurer::{MeasuredElement, MeasuredLength};
+use crate::arranger::ArrangedElement;
+use crate::measurer::{clamp, MeasuredLength};
 use crate::renderer::ArgbColour;
 
 pub enum  PrimaryLength {
@@ -23,29 +26,322 @@ pub enum SecondaryLength {
 pub enum LayoutDirection {
     Row,
     Column,
+    /// Like `Row`, but children that don't fit the height continue in a new line to the right
+    RowWrap,
+    /// Like `Column`, but children that don't fit the width continue in a new line below
+    ColumnWrap,
+    /// Like `Row`, but children are placed from the bottom edge upwards
+    RowReverse,
+    /// Like `Column`, but children are placed from the right edge leftwards
+    ColumnReverse,
+    /// Layers children over each other, each sized and aligned within the whole parent rect on both axes. Neither
+    /// axis is primary, so star lengths aren't valid for a stack's children.
+    Stack,
+    /// Places children in the cells of a grid of `columns` and `rows` tracks. Like a stack, neither axis is primary.
+    Grid,
 }
 
-pub fn generate_layout(root_elem: &MarkupElement, width: f32, height: f32) -> Vec<Rect> {
-    // Dimension - (_dp | content | fill | _*)
+impl LayoutDirection {
+    /// Whether children are arranged vertically, making height the primary measure
+    pub fn is_row(&self) -> bool {
+        matches!(self, LayoutDirection::Row | LayoutDirection::RowWrap | LayoutDirection::RowReverse)
+    }
+
+    pub fn is_wrap(&self) -> bool {
+        matches!(self, LayoutDirection::RowWrap | LayoutDirection::ColumnWrap)
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        matches!(self, LayoutDirection::RowReverse | LayoutDirection::ColumnReverse)
+    }
+
+    pub fn is_stack(&self) -> bool {
+        matches!(self, LayoutDirection::Stack)
+    }
+
+    pub fn is_grid(&self) -> bool {
+        matches!(self, LayoutDirection::Grid)
+    }
+
+    /// Whether `attribute` is the primary measure of children laid out in this direction
+    pub fn is_primary(&self, attribute: &str) -> bool {
+        !self.is_stack() && !self.is_grid() && self.primary_attribute() == attribute
+    }
+
+    pub fn primary_axis(&self) -> Axis {
+        if self.is_row() { Axis::Vertical } else { Axis::Horizontal }
+    }
+
+    pub fn secondary_axis(&self) -> Axis {
+        if self.is_row() { Axis::Horizontal } else { Axis::Vertical }
+    }
+
+    pub fn primary_attribute(&self) -> &'static str {
+        if self.is_row() { "height" } else { "width" }
+    }
+
+    pub fn secondary_attribute(&self) -> &'static str {
+        if self.is_row() { "width" } else { "height" }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// A container whose children don't exactly fit it along one axis
+#[derive(Clone, PartialEq, Debug)]
+pub struct OverflowDiagnostic {
+    pub number: i32,
+    pub axis: Axis,
+    /// How far the children extend past the container's inner size, or negative for the space they leave unfilled
+    /// along a row or column's primary axis
+    pub amount: f32,
+}
+
+/// Placement of a child along its parent's secondary axis
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Alignment {
+    Start,
+    Center,
+    End,
+    /// Sizes the child to the parent's secondary size, overriding its own secondary measure
+    Stretch,
+}
+
+/// Distribution of leftover space along a container's primary axis
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Justification {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+/// A grid child's zero-based row and column, when given, and how many tracks it spans
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GridPlacement {
+    pub row: Option<usize>,
+    pub column: Option<usize>,
+    pub row_span: usize,
+    pub column_span: usize,
+}
+
+impl Default for GridPlacement {
+    fn default() -> GridPlacement {
+        GridPlacement { row: None, column: None, row_span: 1, column_span: 1 }
+    }
+}
+
+/// Whether an element is drawn and whether it takes up space
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Visibility {
+    Visible,
+    /// Takes up space as usual, but neither it nor its descendants are drawn
+    Hidden,
+    /// Left out of the layout entirely, as if it weren't in the document
+    Collapsed,
+}
+
+/// What happens to children that don't fit an element
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Overflow {
+    Visible,
+    /// Children are cut off at the element's edges
+    Clip,
+    /// Clips children like `Clip`, but a row or column lays its children out at their content size along its primary
+    /// axis and shows them shifted by its scroll offset
+    Scroll,
+}
+
+/// Whether an element takes part in its parent's layout
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Position {
+    Static,
+    /// Taken out of the parent's layout and placed against the parent's rect by its `left`, `top`, `right` and
+    /// `bottom` offsets instead. Neither axis is primary.
+    Absolute,
+}
+
+/// Optional per-side dp offsets of an absolutely positioned element from its parent's edges
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Offsets {
+    pub left: Option<f32>,
+    pub top: Option<f32>,
+    pub right: Option<f32>,
+    pub bottom: Option<f32>,
+}
+
+/// Per-side dp sizes for padding and margins
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Edges {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Edges {
+    pub fn horizontal(&self) -> f32 {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> f32 {
+        self.top + self.bottom
+    }
+
+    /// Total of both edges along the primary axis
+    pub fn primary(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.vertical() } else { self.horizontal() }
+    }
+
+    /// Total of both edges along the secondary axis
+    pub fn secondary(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.horizontal() } else { self.vertical() }
+    }
+
+    pub fn primary_start(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.top } else { self.left }
+    }
+
+    pub fn primary_end(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.bottom } else { self.right }
+    }
+
+    pub fn secondary_start(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.left } else { self.top }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum LayoutError {
+    BadLayoutDirection { number: i32, node_name: String, attribute: String, value: String },
+    BadAttributeValue { number: i32, node_name: String, attribute: String, value: String },
+    BadLength { number: i32, node_name: String, attribute: String, value: String },
+    NegativeLength { number: i32, node_name: String, attribute: String, value: String },
+    FillOnPrimaryMeasure { number: i32, node_name: String, attribute: String },
+    StarOnSecondaryMeasure { number: i32, node_name: String, attribute: String },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::BadLayoutDirection { number, node_name, attribute, value } =>
+                write!(f, "Bad {} '{}' on <{}> #{}", attribute, value, node_name, number),
+            LayoutError::BadAttributeValue { number, node_name, attribute, value } =>
+                write!(f, "Bad {} '{}' on <{}> #{}", attribute, value, node_name, number),
+            LayoutError::BadLength { number, node_name, attribute, value } =>
+                write!(f, "Bad length '{}' for {} on <{}> #{}", value, attribute, node_name, number),
+            LayoutError::NegativeLength { number, node_name, attribute, value } =>
+                write!(f, "Negative length '{}' for {} on <{}> #{}", value, attribute, node_name, number),
+            LayoutError::FillOnPrimaryMeasure { number, node_name, attribute } =>
+                write!(f, "Fill not valid as a primary measure ({} on <{}> #{})", attribute, node_name, number),
+            LayoutError::StarOnSecondaryMeasure { number, node_name, attribute } =>
+                write!(f, "Star not valid as a secondary measure ({} on <{}> #{})", attribute, node_name, number),
+        }
+    }
+}
+
+impl Error for LayoutError {}
+
+/// How the root element is sized against the viewport
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RootSizing {
+    /// Star, fill and unspecified root lengths fill the viewport, while dp, percent and content lengths are honoured
+    FillViewport,
+    /// Lays the root out at its content size, e.g. to render a component in isolation
+    Content,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct LayoutOptions {
+    pub root_sizing: RootSizing,
+    /// How far each scroll container, by element number, is scrolled along its primary axis. Offsets are kept
+    /// within the scrollable range when laid out.
+    pub scroll_offsets: HashMap<i32, f32>,
+    /// Device pixels per dp to round element edges to after arrangement, usually the window's scale factor. Rounding
+    /// edges rather than sizes keeps adjacent elements touching. `None` keeps fractional coordinates.
+    pub pixel_snapping: Option<f32>,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> LayoutOptions {
+        LayoutOptions {
+            root_sizing: RootSizing::FillViewport,
+            scroll_offsets: HashMap::new(),
+            pixel_snapping: None,
+        }
+    }
+}
+
+pub fn generate_layout(root_elem: &MarkupElement, width: f32, height: f32) -> Result<Vec<Rect>, LayoutError> {
+    generate_layout_with_options(root_elem, width, height, &LayoutOptions::default())
+}
+
+pub fn generate_layout_with_options(root_elem: &MarkupElement, width: f32, height: f32, options: &LayoutOptions)
+    -> Result<Vec<Rect>, LayoutError> {
+    Ok(layout_tree(root_elem, width, height, options)?.flatten())
+}
+
+/// Lays out a document as a display list, in which the children of `overflow="clip"` and `overflow="scroll"`
+/// elements are clipped by the renderer instead of being cut down to fit
+pub fn generate_display_list(root_elem: &MarkupElement, width: f32, height: f32, options: &LayoutOptions)
+    -> Result<Vec<DrawCommand>, LayoutError> {
+    Ok(layout_tree(root_elem, width, height, options)?.display_list())
+}
+
+/// Lays out a document and reports every container whose children don't fit it, without producing rects
+pub fn detect_overflow(root_elem: &MarkupElement, width: f32, height: f32, options: &LayoutOptions)
+    -> Result<Vec<OverflowDiagnostic>, LayoutError> {
+    Ok(layout_tree(root_elem, width, height, options)?.overflow_diagnostics())
+}
+
+pub(crate) fn layout_tree(root_elem: &MarkupElement, width: f32, height: f32, options: &LayoutOptions)
+    -> Result<ArrangedElement, LayoutError> {
+    // Dimension - (_dp | _% | content | fill | _*)
     // * only allowed on primary measure
     // fill only allowed on secondary measure
-    // * cannot appear below content in visual tree
+    // * and fill children of content-sized elements contribute their own content size
     // content cannot appear on a leaf node (could produce a warning?)
-    // root element always fills the display area
-
-    let measured_tree = MeasuredElement {
-        number: root_elem.number,
-        width: MeasuredLength::Star(1.0),
-        height: MeasuredLength::Star(1.0),
-        layout_direction: root_elem.get_layout_direction(),
-        children: root_elem.children.iter().map(measurer::measure_element_tree).collect()
+    // root element fills the display area (less its margin) unless it has a dp, % or content size
+
+    let mut measured_tree = measurer::measure_root(root_elem)?;
+    if options.root_sizing == RootSizing::Content {
+        measured_tree.content_sized_primary = true;
+    }
+    measured_tree.apply_scroll_offsets(&options.scroll_offsets);
+    let margin = measured_tree.margin;
+    // Percentages and fill both resolve against the viewport less the root's margin, so the root stays within it
+    let root_size = |measure: MeasuredLength, available_size: f32, content_size: f32, (min, max): (f32, f32)| {
+        let size = match (options.root_sizing, measure) {
+            (RootSizing::Content, _) => content_size,
+            (RootSizing::FillViewport, MeasuredLength::Dp(dp)) => dp,
+            (RootSizing::FillViewport, MeasuredLength::Percent(percent)) => available_size * percent / 100.0,
+            (RootSizing::FillViewport, _) => available_size,
+        };
+        clamp(size, min, max)
     };
-    arranger::arrange_layout_tree(&measured_tree, 0.0, 0.0, width, height).flatten()
+    let (available_width, available_height) = (width - margin.horizontal(), height - margin.vertical());
+    let root_width = root_size(measured_tree.width, available_width, measured_tree.content_width,
+                               measured_tree.constraints.width_limits(available_width));
+    let root_height = root_size(measured_tree.height, available_height, measured_tree.content_height,
+                                measured_tree.constraints.height_limits(available_height));
+    let mut arranged_tree = arranger::arrange_layout_tree(&measured_tree, margin.left, margin.top, root_width,
+                                                          root_height)?;
+    if let Some(scale_factor) = options.pixel_snapping.filter(|scale_factor| *scale_factor > 0.0) {
+        arranged_tree.snap_to_pixels(scale_factor);
+    }
+    Ok(arranged_tree)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::arranger::ArrangedElement;
+    use crate::markup;
+    use crate::measurer::{MeasuredElement, SizeConstraints};
 
     use super::*;
 
@@ -67,19 +363,59 @@ mod tests {
                 }
             ],
         };
-        let result = measurer::measure_element_tree(&single_elem_doc);
+        let result = measurer::measure_element_tree(&single_elem_doc, &LayoutDirection::Row).unwrap();
 
         let expected_measured_tree = MeasuredElement {
             number: 0,
-            width: MeasuredLength::Star(1.0),
+            node_name: String::from("imuroot"),
+            width: MeasuredLength::Fill,
             height: MeasuredLength::Star(1.0),
+            content_width: 0.0,
+            content_height: 0.0,
+            content_sized_primary: false,
+            constraints: SizeConstraints::default(),
+            padding: Edges::default(),
+            margin: Edges::default(),
+            position: Position::Static,
+            offsets: Offsets::default(),
+            aspect_ratio: None,
+            grid_placement: GridPlacement::default(),
+            visibility: Visibility::Visible,
+            gap: 0.0,
+            line_gap: 0.0,
+            align: Alignment::Start,
+            align_self: None,
+            justify: Justification::Start,
+            overflow: Overflow::Visible,
+            scroll_offset: 0.0,
             layout_direction: LayoutDirection::Row,
+            grid: None,
             children: vec![
                 MeasuredElement {
                     number: 1,
+                    node_name: String::from("box"),
                     layout_direction: LayoutDirection::Row,
-                    width: MeasuredLength::Star(1.0),
+                    grid: None,
+                    width: MeasuredLength::Fill,
                     height: MeasuredLength::Star(1.0),
+                    content_width: 0.0,
+                    content_height: 0.0,
+                    content_sized_primary: false,
+                    constraints: SizeConstraints::default(),
+                    padding: Edges::default(),
+                    margin: Edges::default(),
+                    position: Position::Static,
+                    offsets: Offsets::default(),
+                    aspect_ratio: None,
+                    grid_placement: GridPlacement::default(),
+                    visibility: Visibility::Visible,
+                    gap: 0.0,
+                    line_gap: 0.0,
+                    align: Alignment::Start,
+                    align_self: None,
+                    justify: Justification::Start,
+                    overflow: Overflow::Visible,
+                    scroll_offset: 0.0,
                     children: vec![],
                 }
             ],
@@ -91,22 +427,62 @@ mod tests {
     fn single_elem_measured_tree_can_be_arranged() {
         let measured_tree = MeasuredElement {
             number: 0,
+            node_name: String::from("imuroot"),
             width: MeasuredLength::Star(1.0),
             height: MeasuredLength::Star(1.0),
+            content_width: 0.0,
+            content_height: 0.0,
+            content_sized_primary: false,
+            constraints: SizeConstraints::default(),
+            padding: Edges::default(),
+            margin: Edges::default(),
+            position: Position::Static,
+            offsets: Offsets::default(),
+            aspect_ratio: None,
+            grid_placement: GridPlacement::default(),
+            visibility: Visibility::Visible,
+            gap: 0.0,
+            line_gap: 0.0,
+            align: Alignment::Start,
+            align_self: None,
+            justify: Justification::Start,
+            overflow: Overflow::Visible,
+            scroll_offset: 0.0,
             layout_direction: LayoutDirection::Row,
+            grid: None,
             children: vec![
                 MeasuredElement {
                     number: 1,
+                    node_name: String::from("box"),
                     layout_direction: LayoutDirection::Row,
-                    width: MeasuredLength::Star(1.0),
+                    grid: None,
+                    width: MeasuredLength::Fill,
                     height: MeasuredLength::Star(1.0),
+                    content_width: 0.0,
+                    content_height: 0.0,
+                    content_sized_primary: false,
+                    constraints: SizeConstraints::default(),
+                    padding: Edges::default(),
+                    margin: Edges::default(),
+                    position: Position::Static,
+                    offsets: Offsets::default(),
+                    aspect_ratio: None,
+                    grid_placement: GridPlacement::default(),
+                    visibility: Visibility::Visible,
+                    gap: 0.0,
+                    line_gap: 0.0,
+                    align: Alignment::Start,
+                    align_self: None,
+                    justify: Justification::Start,
+                    overflow: Overflow::Visible,
+                    scroll_offset: 0.0,
                     children: vec![],
                 }
             ],
         };
         let width = 333.0;
         let height = 777.0;
-        let result = arranger::arrange_layout_tree(&measured_tree, 0.0, 0.0, width, height);
+        let result = arranger::arrange_layout_tree(&measured_tree, 0.0, 0.0, width, height).unwrap();
 
         let expected_arranged_tree = ArrangedElement {
             number: 0,
@@ -114,6 +490,11 @@ mod tests {
             y: 0.0,
             width,
             height,
+            hidden: false,
+            overflow: vec![],
+            overflow_mode: Overflow::Visible,
+            scroll_offset: 0.0,
+            scrolls_from_end: false,
             children: vec![
                 ArrangedElement {
                     number: 1,
@@ -121,10 +502,702 @@ mod tests {
                     y: 0.0,
                     width,
                     height,
+                    hidden: false,
+                    overflow: vec![],
+                    overflow_mode: Overflow::Visible,
+                    scroll_offset: 0.0,
+                    scrolls_from_end: false,
                     children: vec![],
                 }
             ],
         };
         assert_eq!(result, expected_arranged_tree);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn bad_documents_produce_layout_errors() {
+        let bad_direction = markup::parse_document(
+            r#"<imuroot><box layout-direction="diagonal"/></imuroot>"#).unwrap();
+        assert_eq!(generate_layout(&bad_direction, 100.0, 100.0).unwrap_err(), LayoutError::BadLayoutDirection {
+            number: 1,
+            node_name: String::from("box"),
+            attribute: String::from("layout-direction"),
+            value: String::from("diagonal"),
+        });
+
+        let fill_on_primary = markup::parse_document(
+            r#"<imuroot><box/><box height="fill"/></imuroot>"#).unwrap();
+        assert_eq!(generate_layout(&fill_on_primary, 100.0, 100.0).unwrap_err(), LayoutError::FillOnPrimaryMeasure {
+            number: 2,
+            node_name: String::from("box"),
+            attribute: String::from("height"),
+        });
+
+        let star_on_secondary = markup::parse_document(
+            r#"<imuroot><box width="2*"/></imuroot>"#).unwrap();
+        assert_eq!(generate_layout(&star_on_secondary, 100.0, 100.0).unwrap_err(), LayoutError::StarOnSecondaryMeasure {
+            number: 1,
+            node_name: String::from("box"),
+            attribute: String::from("width"),
+        });
+    }
+
+    fn bounds(rects: &[Rect]) -> Vec<(f32, f32, f32, f32)> {
+        rects.iter().map(|rect| (rect.x, rect.y, rect.width, rect.height)).collect()
+    }
+
+    #[test]
+    fn percent_lengths_resolve_against_parent_on_both_axes() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box height="25%"/>
+                <box height="10%" width="50%"/>
+                <box/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 400.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 400.0),
+            (0.0, 0.0, 200.0, 100.0),
+            (0.0, 100.0, 100.0, 40.0),
+            (0.0, 140.0, 200.0, 260.0),
+        ]);
+
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column">
+                <box width="25%" layout-direction="column" height="50%"/>
+                <box layout-direction="column" height="100%"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 400.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 400.0),
+            (0.0, 0.0, 50.0, 200.0),
+            (50.0, 0.0, 150.0, 400.0),
+        ]);
+    }
+
+    #[test]
+    fn star_children_respect_min_and_max_constraints() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column">
+                <box layout-direction="column" max-width="50dp"/>
+                <box layout-direction="column"/>
+                <box layout-direction="column" width="2*"/>
+            </imuroot>"#).unwrap();
+        let widths = |width| bounds(&generate_layout(&doc, width, 100.0).unwrap()).iter()
+            .skip(1).map(|rect| rect.2).collect::<Vec<_>>();
+        // The capped sidebar gives its surplus back to its siblings in proportion to their weights
+        assert_eq!(widths(400.0), vec![50.0, 350.0 / 3.0, 700.0 / 3.0]);
+        assert_eq!(widths(120.0), vec![30.0, 30.0, 60.0]);
+
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column">
+                <box layout-direction="column" min-width="100dp" height="100%"/>
+                <box layout-direction="column" height="100%"/>
+                <box layout-direction="column" width="20dp" max-width="10%" height="50dp" min-height="80%"/>
+            </imuroot>"#).unwrap();
+        // The sidebar takes its minimum from its star sibling rather than collapsing
+        assert_eq!(bounds(&generate_layout(&doc, 150.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 150.0, 100.0),
+            (0.0, 0.0, 100.0, 100.0),
+            (100.0, 0.0, 35.0, 100.0),
+            (135.0, 0.0, 15.0, 80.0),
+        ]);
+
+        // A min violation and a max violation cancelling out still leave both children within their limits
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column">
+                <box layout-direction="column" min-width="70dp"/>
+                <box layout-direction="column" max-width="30dp"/>
+                <box layout-direction="column"/>
+            </imuroot>"#).unwrap();
+        let widths = |width| bounds(&generate_layout(&doc, width, 100.0).unwrap()).iter()
+            .skip(1).map(|rect| rect.2).collect::<Vec<_>>();
+        assert_eq!(widths(150.0), vec![70.0, 30.0, 50.0]);
+        assert_eq!(widths(100.0), vec![70.0, 15.0, 15.0]);
+    }
+
+    #[test]
+    fn padding_and_margins_space_out_children() {
+        let doc = markup::parse_document(r#"
+            <imuroot padding="10dp">
+                <box height="40dp" margin="5dp" margin-bottom="0dp"/>
+                <box padding="4dp" padding-left="20dp" margin-left="6dp">
+                    <box/>
+                    <box height="10dp" width="10dp" margin-top="2dp"/>
+                </box>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 200.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 200.0),
+            (15.0, 15.0, 170.0, 40.0),
+            (16.0, 55.0, 174.0, 135.0),
+            (36.0, 59.0, 150.0, 115.0),
+            (36.0, 176.0, 10.0, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn content_size_includes_padding_and_child_margins() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box height="content" width="content" padding="3dp" padding-top="1dp">
+                    <box height="10dp" width="30dp" margin="2dp"/>
+                    <box height="20dp" width="10dp" margin-left="25dp"/>
+                </box>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 200.0).unwrap())[1], (0.0, 0.0, 41.0, 38.0));
+    }
+
+    #[test]
+    fn gap_spaces_children_along_primary_axis() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column" gap="10dp">
+                <box layout-direction="column" width="40dp" height="100%"/>
+                <box layout-direction="column" height="100%"/>
+                <box layout-direction="column" width="content" height="100%" gap="5dp" margin-left="2dp">
+                    <box layout-direction="column" width="20dp" height="100%"/>
+                    <box layout-direction="column" width="20dp" height="100%"/>
+                </box>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 50.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 50.0),
+            (0.0, 0.0, 40.0, 50.0),
+            (50.0, 0.0, 93.0, 50.0),
+            (155.0, 0.0, 45.0, 50.0),
+            (155.0, 0.0, 20.0, 50.0),
+            (180.0, 0.0, 20.0, 50.0),
+        ]);
+    }
+
+    #[test]
+    fn children_are_aligned_on_secondary_axis() {
+        let doc = markup::parse_document(r#"
+            <imuroot align="center">
+                <box height="10dp" width="40dp"/>
+                <box height="10dp" width="40dp" align-self="end" margin-right="5dp"/>
+                <box height="10dp" width="40dp" align-self="start"/>
+                <box height="10dp" width="40dp" align-self="stretch" margin="10dp" max-width="150dp"/>
+                <box height="10dp"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 100.0),
+            (80.0, 0.0, 40.0, 10.0),
+            (155.0, 10.0, 40.0, 10.0),
+            (0.0, 20.0, 40.0, 10.0),
+            (10.0, 40.0, 150.0, 10.0),
+            (0.0, 60.0, 200.0, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn leftover_primary_space_is_justified() {
+        let tops = |justify: &str| {
+            let doc = markup::parse_document(&format!(r#"
+                <imuroot justify="{}">
+                    <box height="10dp"/>
+                    <box height="20dp" margin-top="5dp"/>
+                    <box height="10dp"/>
+                </imuroot>"#, justify)).unwrap();
+            bounds(&generate_layout(&doc, 100.0, 100.0).unwrap()).iter().skip(1).map(|rect| rect.1).collect::<Vec<_>>()
+        };
+        assert_eq!(tops("start"), vec![0.0, 15.0, 35.0]);
+        assert_eq!(tops("center"), vec![27.5, 42.5, 62.5]);
+        assert_eq!(tops("end"), vec![55.0, 70.0, 90.0]);
+        assert_eq!(tops("space-between"), vec![0.0, 42.5, 90.0]);
+        assert_eq!(tops("space-around"), vec![55.0 / 6.0, 55.0 / 6.0 + 15.0 + 55.0 / 3.0, 55.0 / 6.0 + 35.0 + 110.0 / 3.0]);
+        assert_eq!(tops("space-evenly"), vec![13.75, 42.5, 76.25]);
+    }
+
+    #[test]
+    fn star_children_take_leftover_space_before_justification() {
+        let doc = markup::parse_document(r#"
+            <imuroot justify="end">
+                <box height="10dp"/>
+                <box height="1*" max-height="30dp"/>
+                <box height="0*"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 100.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 60.0, 100.0, 10.0),
+            (0.0, 70.0, 100.0, 30.0),
+            (0.0, 100.0, 100.0, 0.0),
+        ]);
+    }
+
+    #[test]
+    fn lengths_resolve_against_parent_axes() {
+        enum Expected {
+            Size(f32),
+            FillOnPrimary,
+            StarOnSecondary,
+        }
+        let (viewport_width, viewport_height) = (300.0, 200.0);
+        for parent_direction in ["row", "column"].iter() {
+            let is_row = *parent_direction == "row";
+            let (primary_attribute, secondary_attribute) = if is_row { ("height", "width") } else { ("width", "height") };
+            let (primary_size, secondary_size) =
+                if is_row { (viewport_height, viewport_width) } else { (viewport_width, viewport_height) };
+            for child_direction in ["row", "column"].iter() {
+                for &is_primary in [true, false].iter() {
+                    for length in ["40dp", "1*", "fill", "content"].iter() {
+                        let attribute = if is_primary { primary_attribute } else { secondary_attribute };
+                        let doc = markup::parse_document(&format!(r#"
+                            <imuroot layout-direction="{}">
+                                <box layout-direction="{}" {}="{}"><box width="30dp" height="30dp"/></box>
+                                <box/>
+                            </imuroot>"#, parent_direction, child_direction, attribute, length)).unwrap();
+                        let expected = match (is_primary, *length) {
+                            (_, "40dp") => Expected::Size(40.0),
+                            (_, "content") => Expected::Size(30.0),
+                            (true, "1*") => Expected::Size(primary_size / 2.0),
+                            (true, _) => Expected::FillOnPrimary,
+                            (false, "fill") => Expected::Size(secondary_size),
+                            (false, _) => Expected::StarOnSecondary,
+                        };
+                        let case = format!("{} parent, {} child, {}={}", parent_direction, child_direction, attribute, length);
+                        match (generate_layout(&doc, viewport_width, viewport_height), expected) {
+                            (Ok(rects), Expected::Size(size)) => {
+                                let (_, _, width, height) = bounds(&rects)[1];
+                                // The other axis keeps its default: half the primary space or the full secondary size
+                                let (primary, secondary) = if is_primary { (size, secondary_size) } else { (primary_size / 2.0, size) };
+                                let expected_bounds = if is_row { (secondary, primary) } else { (primary, secondary) };
+                                assert_eq!((width, height), expected_bounds, "{}", case);
+                            }
+                            (Err(LayoutError::FillOnPrimaryMeasure { attribute: error_attribute, .. }), Expected::FillOnPrimary) |
+                            (Err(LayoutError::StarOnSecondaryMeasure { attribute: error_attribute, .. }), Expected::StarOnSecondary) =>
+                                assert_eq!(error_attribute, attribute, "{}", case),
+                            (result, _) => panic!("Unexpected result for {}: {:?}", case, result.map(|rects| bounds(&rects))),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn root_is_measured_like_any_other_element() {
+        let doc = markup::parse_document(r#"
+            <imuroot padding="10dp" margin="5dp" height="50%">
+                <box height="20dp" width="30dp"/>
+                <box height="40dp" width="content" padding="4dp"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 300.0).unwrap()), vec![
+            (5.0, 5.0, 190.0, 145.0),
+            (15.0, 15.0, 30.0, 20.0),
+            (15.0, 35.0, 8.0, 40.0),
+        ]);
+
+        let options = LayoutOptions { root_sizing: RootSizing::Content, ..LayoutOptions::default() };
+        assert_eq!(bounds(&generate_layout_with_options(&doc, 200.0, 300.0, &options).unwrap()), vec![
+            (5.0, 5.0, 50.0, 80.0),
+            (15.0, 15.0, 30.0, 20.0),
+            (15.0, 35.0, 8.0, 40.0),
+        ]);
+
+        let star_root = markup::parse_document(r#"<imuroot width="2*" height="fill" max-width="120dp"/>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&star_root, 200.0, 300.0).unwrap()), vec![(0.0, 0.0, 120.0, 300.0)]);
+
+        let percent_root = markup::parse_document(r#"<imuroot width="100%" height="50%" margin="10dp"/>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&percent_root, 100.0, 100.0).unwrap()), vec![(10.0, 10.0, 80.0, 40.0)]);
+    }
+
+    #[test]
+    fn non_finite_sizes_still_lay_out() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column">
+                <box width="1*" min-width="10dp" layout-direction="column"><box/></box>
+                <box width="2*" max-width="20dp"/>
+            </imuroot>"#).unwrap();
+        assert!(generate_layout(&doc, f32::INFINITY, 100.0).is_ok());
+        assert!(generate_layout(&doc, f32::NAN, 100.0).is_ok());
+
+        let huge = markup::parse_document(&format!(
+            r#"<imuroot layout-direction="column"><box width="{}dp" layout-direction="column"><box/></box></imuroot>"#,
+            "9".repeat(60))).unwrap();
+        assert!(generate_layout(&huge, 100.0, 100.0).is_err());
+    }
+
+    #[test]
+    fn content_size_includes_star_childrens_content() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box height="content" width="content" layout-direction="column">
+                    <box width="1*"><box width="30dp" height="10dp"/></box>
+                    <box width="2*" min-width="20dp"/>
+                    <box width="40dp"/>
+                </box>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 200.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 200.0),
+            (0.0, 0.0, 90.0, 10.0),
+            (0.0, 0.0, 30.0, 10.0),
+            (0.0, 0.0, 30.0, 10.0),
+            (30.0, 0.0, 20.0, 10.0),
+            (50.0, 0.0, 40.0, 10.0),
+        ]);
+
+        // Star children of a parent with its own size split it evenly, however large their content
+        let fixed_parent = markup::parse_document(r#"
+            <imuroot layout-direction="column">
+                <box width="1*"><box width="300dp"/></box>
+                <box width="1*"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&fixed_parent, 200.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 100.0),
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 0.0, 300.0, 100.0),
+            (100.0, 0.0, 100.0, 100.0),
+        ]);
+    }
+
+    #[test]
+    fn wrapping_children_continue_on_new_lines() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column-wrap" gap="10dp" line-gap="5dp" align="center">
+                <box width="40dp" height="20dp"/>
+                <box width="40dp" height="10dp"/>
+                <box width="30dp" height="10dp"/>
+                <box height="30dp"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 100.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 11.25, 40.0, 20.0),
+            (50.0, 16.25, 40.0, 10.0),
+            (0.0, 68.75, 30.0, 10.0),
+            (40.0, 58.75, 60.0, 30.0),
+        ]);
+
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="row-wrap">
+                <box height="60dp" width="20dp"/>
+                <box height="60dp" width="fill" min-width="10dp"/>
+                <box height="30dp" width="30dp" align-self="end"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 100.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 0.0, 20.0, 60.0),
+            (45.0, 0.0, 55.0, 60.0),
+            (70.0, 60.0, 30.0, 30.0),
+        ]);
+
+        // Lines share the container's secondary size, so default fill children don't collapse
+        let default_sizes = |count| {
+            let doc = markup::parse_document(&format!(r#"<imuroot layout-direction="row-wrap">{}</imuroot>"#,
+                                                      r#"<box height="60dp"/>"#.repeat(count))).unwrap();
+            bounds(&generate_layout(&doc, 100.0, 100.0).unwrap())
+        };
+        assert_eq!(default_sizes(1), vec![(0.0, 0.0, 100.0, 100.0), (0.0, 0.0, 100.0, 60.0)]);
+        assert_eq!(default_sizes(2), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 0.0, 50.0, 60.0),
+            (50.0, 0.0, 50.0, 60.0),
+        ]);
+    }
+
+    #[test]
+    fn reversed_directions_place_children_from_the_end_edge() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="row-reverse">
+                <box height="20dp" margin-bottom="5dp"/>
+                <box height="30dp"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 100.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 75.0, 100.0, 20.0),
+            (0.0, 45.0, 100.0, 30.0),
+        ]);
+
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column-reverse" gap="10dp" justify="end">
+                <box width="20dp" height="fill"/>
+                <box width="30dp" height="fill"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 100.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (40.0, 0.0, 20.0, 100.0),
+            (0.0, 0.0, 30.0, 100.0),
+        ]);
+    }
+
+    #[test]
+    fn stack_layers_children_over_the_whole_parent() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="stack" padding="10dp" align="center">
+                <box/>
+                <box width="20dp" height="50%" align-self="end" margin-right="5dp"/>
+                <box width="content" height="content" layout-direction="stack">
+                    <box width="30dp" height="10dp"/>
+                    <box width="10dp" height="20dp" margin="2dp"/>
+                </box>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 120.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 120.0, 100.0),
+            (10.0, 10.0, 100.0, 80.0),
+            (85.0, 50.0, 20.0, 40.0),
+            (45.0, 38.0, 30.0, 24.0),
+            (45.0, 38.0, 30.0, 10.0),
+            (47.0, 40.0, 10.0, 20.0),
+        ]);
+    }
+
+    #[test]
+    fn grid_places_children_in_tracks() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="grid" columns="40dp 1* 2*" rows="content 1*" gap="5dp">
+                <box height="20dp"/>
+                <box grid-column-span="2"/>
+                <box grid-row="1" grid-column="2" width="50%" align-self="center"/>
+                <box/>
+                <box grid-row-span="2"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 100.0),
+            (0.0, 0.0, 40.0, 20.0),
+            (45.0, 0.0, 155.0, 20.0),
+            (125.0, 25.0, 50.0, 70.0),
+            (0.0, 25.0, 40.0, 70.0),
+            (45.0, 25.0, 50.0, 75.0),
+        ]);
+    }
+
+    #[test]
+    fn grid_placements_must_fit_within_the_grid() {
+        let layout = |child: &str| {
+            let doc = markup::parse_document(
+                &format!(r#"<imuroot layout-direction="grid" columns="1* 1*" rows="1*">{}</imuroot>"#, child)).unwrap();
+            generate_layout(&doc, 100.0, 100.0).map(|rects| bounds(&rects))
+        };
+        let bad_value = |attribute: &str, value: &str| Err(LayoutError::BadAttributeValue {
+            number: 1,
+            node_name: String::from("box"),
+            attribute: String::from(attribute),
+            value: String::from(value),
+        });
+        assert_eq!(layout(r#"<box grid-row="1" grid-row-span="18446744073709551615"/>"#),
+                   bad_value("grid-row-span", "18446744073709551615"));
+        assert_eq!(layout(r#"<box grid-row="100000000"/>"#), bad_value("grid-row", "100000000"));
+        assert_eq!(layout(r#"<box grid-column="5"/>"#), bad_value("grid-column", "5"));
+        assert_eq!(layout(r#"<box grid-column="1" grid-column-span="3"/>"#),
+                   Ok(vec![(0.0, 0.0, 100.0, 100.0), (50.0, 0.0, 50.0, 100.0)]));
+    }
+
+    #[test]
+    fn absolute_children_are_placed_against_the_parent_rect() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column" padding="10dp">
+                <box width="50dp"/>
+                <box position="absolute" right="10dp" bottom="10dp" width="40dp" height="20dp"/>
+                <box width="1*"/>
+                <box position="absolute" left="5dp" right="5dp" height="10dp"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 200.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 200.0, 100.0),
+            (10.0, 10.0, 50.0, 80.0),
+            (150.0, 70.0, 40.0, 20.0),
+            (60.0, 10.0, 130.0, 80.0),
+            (5.0, 0.0, 190.0, 10.0),
+        ]);
+
+        // Paint order follows the document, whatever the element numbers
+        let mut renumbered = markup::parse_document(r#"
+            <imuroot layout-direction="stack">
+                <box width="10dp" height="10dp"/>
+                <box position="absolute" width="20dp" height="20dp"/>
+                <box width="30dp" height="30dp"/>
+            </imuroot>"#).unwrap();
+        renumbered.children[0].number = 5;
+        let widths: Vec<f32> = bounds(&generate_layout(&renumbered, 100.0, 100.0).unwrap()).iter()
+            .map(|rect| rect.2)
+            .collect();
+        assert_eq!(widths, vec![100.0, 10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    fn aspect_ratio_derives_one_dimension_from_the_other() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box aspect-ratio="16:9"/>
+                <box/>
+                <box height="45dp" aspect-ratio="2"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 160.0, 300.0).unwrap()), vec![
+            (0.0, 0.0, 160.0, 300.0),
+            (0.0, 0.0, 160.0, 90.0),
+            (0.0, 90.0, 160.0, 165.0),
+            (0.0, 255.0, 90.0, 45.0),
+        ]);
+
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column" align="center">
+                <box height="fill"/>
+                <box height="50dp" aspect-ratio="1:1"/>
+                <box width="40dp" height="fill" aspect-ratio="2:1"/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 300.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 300.0, 100.0),
+            (0.0, 0.0, 210.0, 100.0),
+            (210.0, 25.0, 50.0, 50.0),
+            (260.0, 40.0, 40.0, 20.0),
+        ]);
+    }
+
+    #[test]
+    fn hidden_elements_keep_their_space_and_collapsed_ones_dont() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box height="10dp" visibility="hidden"><box/></box>
+                <box height="20dp" visibility="collapsed"/>
+                <box height="30dp"/>
+                <box/>
+            </imuroot>"#).unwrap();
+        assert_eq!(bounds(&generate_layout(&doc, 100.0, 100.0).unwrap()), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 10.0, 100.0, 30.0),
+            (0.0, 40.0, 100.0, 60.0),
+        ]);
+    }
+
+    #[test]
+    fn overflow_and_underflow_are_reported_per_container() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box height="80dp"/>
+                <box height="40dp"/>
+                <box/>
+                <box height="10dp" width="120dp"/>
+                <box height="20dp" layout-direction="column"><box width="30dp"/></box>
+            </imuroot>"#).unwrap();
+        let options = LayoutOptions::default();
+        assert_eq!(detect_overflow(&doc, 100.0, 100.0, &options).unwrap(), vec![
+            OverflowDiagnostic { number: 0, axis: Axis::Vertical, amount: 50.0 },
+            OverflowDiagnostic { number: 0, axis: Axis::Horizontal, amount: 20.0 },
+            OverflowDiagnostic { number: 5, axis: Axis::Horizontal, amount: -70.0 },
+        ]);
+        assert_eq!(bounds(&generate_layout(&doc, 100.0, 100.0).unwrap())[3], (0.0, 120.0, 100.0, 0.0));
+    }
+
+    #[test]
+    fn scroll_containers_offset_and_clip_their_children() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box height="50dp" overflow="scroll">
+                    <box height="30dp"/>
+                    <box height="30dp"/>
+                    <box height="30dp"/>
+                    <box height="30dp"/>
+                </box>
+                <box height="20dp" overflow="clip" layout-direction="column"><box width="150dp"/></box>
+            </imuroot>"#).unwrap();
+        let mut options = LayoutOptions::default();
+        options.scroll_offsets.insert(1, 20.0);
+        assert_eq!(bounds(&generate_layout_with_options(&doc, 100.0, 100.0, &options).unwrap()), vec![
+            (0.0, 0.0, 100.0, 100.0),
+            (0.0, 0.0, 100.0, 50.0),
+            (0.0, 0.0, 100.0, 10.0),
+            (0.0, 10.0, 100.0, 30.0),
+            (0.0, 40.0, 100.0, 10.0),
+            (0.0, 50.0, 100.0, 20.0),
+            (0.0, 50.0, 100.0, 20.0),
+        ]);
+
+        options.scroll_offsets.insert(1, 500.0);
+        let tree = layout_tree(&doc, 100.0, 100.0, &options).unwrap();
+        let container = |x, y| {
+            tree.scroll_container_at(x, y).map(|container| (container.number, container.scroll_offset))
+        };
+        assert_eq!(container(10.0, 10.0), Some((1, 70.0)));
+        assert_eq!(container(10.0, 60.0), None);
+        assert_eq!(bounds(&tree.flatten())[2..4], [(0.0, 0.0, 100.0, 20.0), (0.0, 20.0, 100.0, 30.0)]);
+    }
+
+    #[test]
+    fn wrapped_and_reversed_containers_scroll_from_their_first_children() {
+        let scrolled = |doc: &MarkupElement, offset| {
+            let mut options = LayoutOptions::default();
+            options.scroll_offsets.insert(1, offset);
+            bounds(&generate_layout_with_options(doc, 100.0, 100.0, &options).unwrap())[2..].to_vec()
+        };
+
+        // Wrapped lines stack up along the secondary axis, so that's the one that scrolls
+        let wrapped = markup::parse_document(r#"
+            <imuroot>
+                <box height="50dp" layout-direction="column-wrap" overflow="scroll">
+                    <box width="60dp" height="20dp"/>
+                    <box width="60dp" height="20dp"/>
+                    <box width="60dp" height="20dp"/>
+                </box>
+            </imuroot>"#).unwrap();
+        assert_eq!(scrolled(&wrapped, 0.0), vec![
+            (0.0, 0.0, 60.0, 20.0),
+            (0.0, 20.0, 60.0, 20.0),
+            (0.0, 40.0, 60.0, 10.0),
+        ]);
+        assert_eq!(scrolled(&wrapped, 100.0), vec![
+            (0.0, 0.0, 60.0, 10.0),
+            (0.0, 10.0, 60.0, 20.0),
+            (0.0, 30.0, 60.0, 20.0),
+        ]);
+
+        // Reversed children are anchored to the end edge, which is where an offset of 0 shows
+        let reversed = markup::parse_document(r#"
+            <imuroot>
+                <box height="50dp" layout-direction="row-reverse" overflow="scroll">
+                    <box height="30dp"/>
+                    <box height="30dp"/>
+                    <box height="30dp"/>
+                    <box height="30dp"/>
+                </box>
+            </imuroot>"#).unwrap();
+        assert_eq!(scrolled(&reversed, 0.0), vec![(0.0, 20.0, 100.0, 30.0), (0.0, 0.0, 100.0, 20.0)]);
+        assert_eq!(scrolled(&reversed, 70.0), vec![(0.0, 30.0, 100.0, 20.0), (0.0, 0.0, 100.0, 30.0)]);
+    }
+
+    #[test]
+    fn display_list_clips_the_children_of_clipping_containers() {
+        let doc = markup::parse_document(r#"
+            <imuroot>
+                <box height="20dp" overflow="clip" layout-direction="column"><box width="150dp"/></box>
+                <box height="20dp" layout-direction="column"><box width="150dp"/></box>
+            </imuroot>"#).unwrap();
+        let commands = generate_display_list(&doc, 100.0, 100.0, &LayoutOptions::default()).unwrap();
+        let summary: Vec<String> = commands.iter()
+            .map(|command| match command {
+                DrawCommand::Rect(rect) => format!("rect {} {} {} {}", rect.x, rect.y, rect.width, rect.height),
+                DrawCommand::PushClip { x, y, width, height } => format!("clip {} {} {} {}", x, y, width, height),
+                DrawCommand::PopClip => String::from("pop"),
+            })
+            .collect();
+        assert_eq!(summary, vec![
+            "rect 0 0 100 100",
+            "rect 0 0 100 20",
+            "clip 0 0 100 20",
+            "rect 0 0 150 20",
+            "pop",
+            "rect 0 20 100 20",
+            "rect 0 20 150 20",
+        ]);
+    }
+
+    #[test]
+    fn pixel_snapping_rounds_edges_without_gaps() {
+        let doc = markup::parse_document(r#"
+            <imuroot layout-direction="column">
+                <box width="1*"/>
+                <box width="1*"/>
+                <box width="1*"/>
+            </imuroot>"#).unwrap();
+        let snapped = |scale_factor| {
+            let options = LayoutOptions { pixel_snapping: Some(scale_factor), ..LayoutOptions::default() };
+            bounds(&generate_layout_with_options(&doc, 100.0, 10.0, &options).unwrap())
+        };
+        assert_eq!(snapped(1.0), vec![
+            (0.0, 0.0, 100.0, 10.0),
+            (0.0, 0.0, 33.0, 10.0),
+            (33.0, 0.0, 34.0, 10.0),
+            (67.0, 0.0, 33.0, 10.0),
+        ]);
+        assert_eq!(snapped(2.0), vec![
+            (0.0, 0.0, 100.0, 10.0),
+            (0.0, 0.0, 33.5, 10.0),
+            (33.5, 0.0, 33.0, 10.0),
+            (66.5, 0.0, 33.5, 10.0),
+        ]);
+    }
+}
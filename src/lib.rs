@@ -4,6 +4,14 @@ mod layout;
 mod markup;
 mod arranger;
 mod measurer;
+mod validator;
+mod grid;
 
 pub use face_window::run_face_window;
-pub use markup::MarkupElement;
\ No newline at end of file
+pub use layout::{
+    detect_overflow, generate_display_list, generate_layout, generate_layout_with_options, Axis, LayoutError, LayoutOptions,
+    OverflowDiagnostic, RootSizing,
+};
+pub use markup::{MarkupElement, MarkupParseError, parse_document, print_document};
+pub use renderer::{ArgbColour, ColourParseError, DrawCommand, Rect};
+pub use validator::{Diagnostic, Severity, validate};
\ No newline at end of file
@@ -1,16 +1,26 @@
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::OnceLock;
 
 use regex::Regex;
 
-use crate::layout::LayoutDirection;
+use crate::grid::MAX_GRID_TRACKS;
+use crate::layout::{
+    Alignment, Edges, GridPlacement, Justification, LayoutDirection, LayoutError, Offsets, Overflow, Position,
+    Visibility,
+};
 
+#[derive(Debug, PartialEq)]
 pub enum MarkupLength {
     Dp(f32),
     Star(f32),
+    Percent(f32),
     Content,
     Fill,
 }
 
+#[derive(Debug, PartialEq)]
 pub struct MarkupElement {
     pub node_name: String,
     pub number: i32,
@@ -19,57 +29,696 @@ pub struct MarkupElement {
 }
 
 impl MarkupElement {
-    pub fn get_layout_direction(&self) -> LayoutDirection {
-        self.attributes.get("layout-direction").map(|dir_str|
-            match &dir_str[..] {
-                "column" => LayoutDirection::Column,
-                "row" => LayoutDirection::Row,
-                _ => panic!("Bad layout-direction on {}", &self.node_name)
-            }
-        ).unwrap_or(LayoutDirection::Row)
+    pub fn get_layout_direction(&self) -> Result<LayoutDirection, LayoutError> {
+        match self.attributes.get("layout-direction").map(|dir_str| &dir_str[..]) {
+            Some("column") => Ok(LayoutDirection::Column),
+            Some("row") | None => Ok(LayoutDirection::Row),
+            Some("row-wrap") => Ok(LayoutDirection::RowWrap),
+            Some("column-wrap") => Ok(LayoutDirection::ColumnWrap),
+            Some("row-reverse") => Ok(LayoutDirection::RowReverse),
+            Some("column-reverse") => Ok(LayoutDirection::ColumnReverse),
+            Some("stack") => Ok(LayoutDirection::Stack),
+            Some("grid") => Ok(LayoutDirection::Grid),
+            Some(dir_str) => Err(LayoutError::BadLayoutDirection {
+                number: self.number,
+                node_name: self.node_name.clone(),
+                attribute: String::from("layout-direction"),
+                value: String::from(dir_str),
+            })
+        }
+    }
+
+    pub fn get_primary_measure(&self, layout_direction: &LayoutDirection) -> Result<Option<MarkupLength>, LayoutError> {
+        self.get_length(layout_direction.primary_attribute())
+    }
+
+    pub fn get_secondary_measure(&self, layout_direction: &LayoutDirection) -> Result<Option<MarkupLength>, LayoutError> {
+        self.get_length(layout_direction.secondary_attribute())
+    }
+
+    pub fn get_width(&self) -> Result<Option<MarkupLength>, LayoutError> {
+        self.get_length("width")
+    }
+
+    pub fn get_height(&self) -> Result<Option<MarkupLength>, LayoutError> {
+        self.get_length("height")
     }
 
-    pub fn get_primary_measure(&self, layout_direction: &LayoutDirection) -> Option<MarkupLength> {
-        match layout_direction {
-            LayoutDirection::Row => self.get_height(),
-            LayoutDirection::Column => self.get_width()
+    pub fn get_padding(&self) -> Result<Edges, LayoutError> {
+        self.get_edges("padding")
+    }
+
+    pub fn get_margin(&self) -> Result<Edges, LayoutError> {
+        self.get_edges("margin")
+    }
+
+    pub fn get_align(&self) -> Result<Alignment, LayoutError> {
+        Ok(self.get_alignment("align")?.unwrap_or(Alignment::Start))
+    }
+
+    pub fn get_align_self(&self) -> Result<Option<Alignment>, LayoutError> {
+        self.get_alignment("align-self")
+    }
+
+    pub fn get_justify(&self) -> Result<Justification, LayoutError> {
+        match self.attributes.get("justify").map(|justify_str| justify_str.trim()) {
+            Some("start") | None => Ok(Justification::Start),
+            Some("center") => Ok(Justification::Center),
+            Some("end") => Ok(Justification::End),
+            Some("space-between") => Ok(Justification::SpaceBetween),
+            Some("space-around") => Ok(Justification::SpaceAround),
+            Some("space-evenly") => Ok(Justification::SpaceEvenly),
+            Some(_) => Err(self.bad_attribute_value("justify")),
         }
     }
 
-    pub fn get_secondary_measure(&self, layout_direction: &LayoutDirection) -> Option<MarkupLength> {
-        match layout_direction {
-            LayoutDirection::Row => self.get_width(),
-            LayoutDirection::Column => self.get_height()
+    fn get_alignment(&self, attribute: &str) -> Result<Option<Alignment>, LayoutError> {
+        match self.attributes.get(attribute).map(|align_str| align_str.trim()) {
+            Some("start") => Ok(Some(Alignment::Start)),
+            Some("center") => Ok(Some(Alignment::Center)),
+            Some("end") => Ok(Some(Alignment::End)),
+            Some("stretch") => Ok(Some(Alignment::Stretch)),
+            Some(_) => Err(self.bad_attribute_value(attribute)),
+            None => Ok(None),
         }
     }
 
-    pub fn get_width(&self) -> Option<MarkupLength> {
-        self.attributes.get("width").and_then(parse_length)
+    pub(crate) fn bad_attribute_value(&self, attribute: &str) -> LayoutError {
+        LayoutError::BadAttributeValue {
+            number: self.number,
+            node_name: self.node_name.clone(),
+            attribute: String::from(attribute),
+            value: self.attributes[attribute].clone(),
+        }
     }
 
-    pub fn get_height(&self) -> Option<MarkupLength> {
-        self.attributes.get("height").and_then(parse_length)
+    pub fn get_gap(&self) -> Result<f32, LayoutError> {
+        Ok(self.get_dp("gap")?.unwrap_or(0.0))
     }
+
+    /// The space between lines of a wrapping container, along its secondary axis
+    pub fn get_line_gap(&self) -> Result<f32, LayoutError> {
+        Ok(self.get_dp("line-gap")?.unwrap_or(0.0))
+    }
+
+    pub fn get_position(&self) -> Result<Position, LayoutError> {
+        match self.attributes.get("position").map(|position_str| position_str.trim()) {
+            Some("static") | None => Ok(Position::Static),
+            Some("absolute") => Ok(Position::Absolute),
+            Some(_) => Err(self.bad_attribute_value("position")),
+        }
+    }
+
+    pub fn get_visibility(&self) -> Result<Visibility, LayoutError> {
+        match self.attributes.get("visibility").map(|visibility_str| visibility_str.trim()) {
+            Some("visible") | None => Ok(Visibility::Visible),
+            Some("hidden") => Ok(Visibility::Hidden),
+            Some("collapsed") => Ok(Visibility::Collapsed),
+            Some(_) => Err(self.bad_attribute_value("visibility")),
+        }
+    }
+
+    pub fn get_overflow(&self) -> Result<Overflow, LayoutError> {
+        match self.attributes.get("overflow").map(|overflow_str| overflow_str.trim()) {
+            Some("visible") | None => Ok(Overflow::Visible),
+            Some("clip") => Ok(Overflow::Clip),
+            Some("scroll") => Ok(Overflow::Scroll),
+            Some(_) => Err(self.bad_attribute_value("overflow")),
+        }
+    }
+
+    pub fn get_offsets(&self) -> Result<Offsets, LayoutError> {
+        Ok(Offsets {
+            left: self.get_dp("left")?,
+            top: self.get_dp("top")?,
+            right: self.get_dp("right")?,
+            bottom: self.get_dp("bottom")?,
+        })
+    }
+
+    /// Width divided by height, from a `width:height` pair such as `16:9` or a single number such as `1.5`
+    pub fn get_aspect_ratio(&self) -> Result<Option<f32>, LayoutError> {
+        let ratio_str = match self.attributes.get("aspect-ratio") {
+            Some(ratio_str) => ratio_str,
+            None => return Ok(None),
+        };
+        let parse = |number_str: &str| number_str.trim().parse::<f32>().ok().filter(|number| number.is_finite());
+        let ratio = match ratio_str.split_once(':') {
+            Some((width_str, height_str)) =>
+                parse(width_str).zip(parse(height_str)).map(|(width, height)| width / height),
+            None => parse(ratio_str),
+        };
+        match ratio {
+            Some(ratio) if ratio > 0.0 && ratio.is_finite() => Ok(Some(ratio)),
+            _ => Err(self.bad_attribute_value("aspect-ratio")),
+        }
+    }
+
+    /// The column tracks of a grid, e.g. `200dp 1* 2*`
+    pub fn get_columns(&self) -> Result<Vec<MarkupLength>, LayoutError> {
+        self.get_tracks("columns")
+    }
+
+    pub fn get_rows(&self) -> Result<Vec<MarkupLength>, LayoutError> {
+        self.get_tracks("rows")
+    }
+
+    /// Where a grid's child goes, from `grid-row`, `grid-column`, `grid-row-span` and `grid-column-span`. Placements
+    /// must end within `MAX_GRID_TRACKS` rows and columns.
+    pub fn get_grid_placement(&self) -> Result<GridPlacement, LayoutError> {
+        let get_span = |attribute: &str| match self.get_index(attribute)? {
+            Some(0) => Err(self.bad_attribute_value(attribute)),
+            span => Ok(span.unwrap_or(1)),
+        };
+        // Each track up to the end of the placement gets allocated, so the end has to stay within the limit
+        let check_end = |index_attribute: &str, index: Option<usize>, span_attribute: &str, span: usize| {
+            if index.is_some_and(|index| index >= MAX_GRID_TRACKS) {
+                Err(self.bad_attribute_value(index_attribute))
+            } else if index.unwrap_or(0).checked_add(span).is_none_or(|end| end > MAX_GRID_TRACKS) {
+                Err(self.bad_attribute_value(span_attribute))
+            } else {
+                Ok(())
+            }
+        };
+        let placement = GridPlacement {
+            row: self.get_index("grid-row")?,
+            column: self.get_index("grid-column")?,
+            row_span: get_span("grid-row-span")?,
+            column_span: get_span("grid-column-span")?,
+        };
+        check_end("grid-row", placement.row, "grid-row-span", placement.row_span)?;
+        check_end("grid-column", placement.column, "grid-column-span", placement.column_span)?;
+        Ok(placement)
+    }
+
+    fn get_index(&self, attribute: &str) -> Result<Option<usize>, LayoutError> {
+        match self.attributes.get(attribute) {
+            Some(index_str) => index_str.trim().parse().map(Some).map_err(|_| self.bad_attribute_value(attribute)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a whitespace-separated list of dp, star, percent or content lengths
+    fn get_tracks(&self, attribute: &str) -> Result<Vec<MarkupLength>, LayoutError> {
+        let tracks_str = match self.attributes.get(attribute) {
+            Some(tracks_str) => tracks_str,
+            None => return Ok(vec![]),
+        };
+        tracks_str.split_whitespace()
+            .map(|track_str| {
+                let (number, node_name, attribute, value) =
+                    (self.number, self.node_name.clone(), String::from(attribute), tracks_str.clone());
+                match parse_length(track_str) {
+                    Ok(MarkupLength::Fill) | Err(LengthParseError::Malformed) =>
+                        Err(LayoutError::BadLength { number, node_name, attribute, value }),
+                    Err(LengthParseError::Negative) =>
+                        Err(LayoutError::NegativeLength { number, node_name, attribute, value }),
+                    Ok(length) => Ok(length),
+                }
+            })
+            .collect()
+    }
+
+    /// Reads a uniform dp size from `attribute`, overridden per side by `attribute-left`, `attribute-top`, etc.
+    fn get_edges(&self, attribute: &str) -> Result<Edges, LayoutError> {
+        let uniform = self.get_dp(attribute)?.unwrap_or(0.0);
+        Ok(Edges {
+            left: self.get_dp(&format!("{}-left", attribute))?.unwrap_or(uniform),
+            top: self.get_dp(&format!("{}-top", attribute))?.unwrap_or(uniform),
+            right: self.get_dp(&format!("{}-right", attribute))?.unwrap_or(uniform),
+            bottom: self.get_dp(&format!("{}-bottom", attribute))?.unwrap_or(uniform),
+        })
+    }
+
+    fn get_dp(&self, attribute: &str) -> Result<Option<f32>, LayoutError> {
+        match self.get_length(attribute)? {
+            Some(MarkupLength::Dp(dp)) => Ok(Some(dp)),
+            Some(_) => Err(LayoutError::BadLength {
+                number: self.number,
+                node_name: self.node_name.clone(),
+                attribute: String::from(attribute),
+                value: self.attributes[attribute].clone(),
+            }),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_length(&self, attribute: &str) -> Result<Option<MarkupLength>, LayoutError> {
+        match self.attributes.get(attribute) {
+            Some(length_string) => parse_length(length_string).map(Some).map_err(|err| {
+                let (number, node_name, attribute, value) =
+                    (self.number, self.node_name.clone(), String::from(attribute), length_string.clone());
+                match err {
+                    LengthParseError::Malformed => LayoutError::BadLength { number, node_name, attribute, value },
+                    LengthParseError::Negative => LayoutError::NegativeLength { number, node_name, attribute, value },
+                }
+            }),
+            None => Ok(None)
+        }
+    }
+}
+
+enum LengthParseError {
+    Malformed,
+    Negative,
 }
 
-fn parse_length(length_string: &String) -> Option<MarkupLength> {
-    match &length_string[..] {
-        "content" => Some(MarkupLength::Content),
-        "fill" => Some(MarkupLength::Fill),
+/// Parses `content`, `fill` or a non-negative decimal followed by a `dp`, `*` or `%` unit, e.g. `12.5dp` or `.5*`.
+/// Surrounding whitespace and whitespace between the value and unit are ignored.
+fn parse_length(length_string: &str) -> Result<MarkupLength, LengthParseError> {
+    match length_string.trim() {
+        "content" => Ok(MarkupLength::Content),
+        "fill" => Ok(MarkupLength::Fill),
         str => {
-            let re = Regex::new(r"^(?P<value>[0-9]+)(?P<unit>dp|\*)$").unwrap();
-            re.captures(str).map(|c| match (c.name("value"), c.name("unit")) {
-                (Some(value_match), Some(unit_match)) => {
-                    let unit_str = unit_match.as_str();
-                    let value = value_match.as_str().parse::<f32>().unwrap();
-                    match &unit_str[..] {
-                        "dp" => MarkupLength::Dp(value),
-                        "*" => MarkupLength::Star(value),
-                        _ => panic!("Bad length unit '{}'", unit_str)
+            // Compiled once, since lengths are parsed for every attribute on every layout
+            static LENGTH_RE: OnceLock<Regex> = OnceLock::new();
+            let re = LENGTH_RE.get_or_init(|| {
+                Regex::new(r"^(?P<sign>[-+]?)(?P<value>[0-9]+(\.[0-9]*)?|\.[0-9]+)\s*(?P<unit>dp|\*|%)$").unwrap()
+            });
+            let captures = re.captures(str).ok_or(LengthParseError::Malformed)?;
+            // Long enough digit strings parse as infinity, which no length can be
+            let value = captures["value"].parse::<f32>().ok()
+                .filter(|value| value.is_finite())
+                .ok_or(LengthParseError::Malformed)?;
+            if &captures["sign"] == "-" && value != 0.0 {
+                return Err(LengthParseError::Negative);
+            }
+            match &captures["unit"] {
+                "dp" => Ok(MarkupLength::Dp(value)),
+                "*" => Ok(MarkupLength::Star(value)),
+                "%" => Ok(MarkupLength::Percent(value)),
+                _ => Err(LengthParseError::Malformed)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MarkupParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MarkupParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl Error for MarkupParseError {}
+
+/// Parses a markup document such as `<imuroot version="0.0.0"><box height="40dp"/></imuroot>`
+/// into its root element. Elements are numbered in document order, starting at 0 for the root.
+pub fn parse_document(document: &str) -> Result<MarkupElement, MarkupParseError> {
+    let mut parser = MarkupParser::new(document);
+    parser.skip_misc()?;
+    if parser.peek().is_none() {
+        return Err(parser.error("Expected a root element"));
+    }
+    let root = parser.parse_element()?;
+    parser.skip_misc()?;
+    match parser.peek() {
+        None => Ok(root),
+        Some(_) => Err(parser.error("Unexpected content after the root element")),
+    }
+}
+
+struct MarkupParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    next_number: i32,
+}
+
+impl MarkupParser {
+    fn new(document: &str) -> MarkupParser {
+        MarkupParser {
+            chars: document.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+            next_number: 0,
+        }
+    }
+
+    fn error(&self, message: &str) -> MarkupParseError {
+        MarkupParseError {
+            line: self.line,
+            column: self.column,
+            message: String::from(message),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), MarkupParseError> {
+        if self.starts_with(s) {
+            s.chars().for_each(|_| { self.advance(); });
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", s)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.advance();
+        }
+    }
+
+    /// Skips whitespace, comments and `<?...?>` declarations between elements
+    fn skip_misc(&mut self) -> Result<(), MarkupParseError> {
+        loop {
+            self.skip_whitespace();
+            if self.starts_with("<!--") {
+                let (line, column) = (self.line, self.column);
+                while !self.starts_with("-->") {
+                    if self.advance().is_none() {
+                        return Err(MarkupParseError { line, column, message: String::from("Unterminated comment") });
                     }
                 }
-                (_, _) => panic!("Bad length value '{}'", length_string)
-            })
+                self.expect("-->")?;
+            } else if self.starts_with("<?") {
+                let (line, column) = (self.line, self.column);
+                while !self.starts_with("?>") {
+                    if self.advance().is_none() {
+                        return Err(MarkupParseError { line, column, message: String::from("Unterminated declaration") });
+                    }
+                }
+                self.expect("?>")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, MarkupParseError> {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == ':' || c == '.' {
+                name.push(c);
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        if name.is_empty() || !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            Err(self.error("Expected a name"))
+        } else {
+            Ok(name)
+        }
+    }
+
+    fn parse_attribute_value(&mut self) -> Result<String, MarkupParseError> {
+        let quote = match self.peek() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => return Err(self.error("Expected a quoted attribute value")),
+        };
+        self.advance();
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None | Some('<') => return Err(self.error("Unterminated attribute value")),
+                Some(c) if c == quote => {
+                    self.advance();
+                    return Ok(value);
+                }
+                Some('&') => value.push(self.parse_entity()?),
+                Some(c) => {
+                    value.push(c);
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    fn parse_entity(&mut self) -> Result<char, MarkupParseError> {
+        let entity_error = self.error("Unknown entity");
+        self.expect("&")?;
+        let mut entity = String::new();
+        while let Some(c) = self.advance() {
+            if c == ';' {
+                return match &entity[..] {
+                    "amp" => Ok('&'),
+                    "lt" => Ok('<'),
+                    "gt" => Ok('>'),
+                    "quot" => Ok('"'),
+                    "apos" => Ok('\''),
+                    _ => Err(entity_error),
+                };
+            }
+            entity.push(c);
+        }
+        Err(entity_error)
+    }
+
+    fn parse_element(&mut self) -> Result<MarkupElement, MarkupParseError> {
+        self.expect("<")?;
+        let node_name = self.parse_name()?;
+        let number = self.next_number;
+        self.next_number += 1;
+
+        let mut attributes = HashMap::new();
+        loop {
+            self.skip_whitespace();
+            if self.starts_with("/>") {
+                self.expect("/>")?;
+                return Ok(MarkupElement { node_name, number, attributes, children: vec![] });
+            }
+            if self.starts_with(">") {
+                self.expect(">")?;
+                break;
+            }
+            let (line, column) = (self.line, self.column);
+            let attribute_name = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let value = self.parse_attribute_value()?;
+            if attributes.insert(attribute_name.clone(), value).is_some() {
+                return Err(MarkupParseError {
+                    line,
+                    column,
+                    message: format!("Duplicate attribute '{}' on <{}>", attribute_name, node_name),
+                });
+            }
+        }
+
+        let mut children = vec![];
+        loop {
+            self.skip_misc()?;
+            if self.starts_with("</") {
+                let (line, column) = (self.line, self.column);
+                self.expect("</")?;
+                let closing_name = self.parse_name()?;
+                if closing_name != node_name {
+                    return Err(MarkupParseError {
+                        line,
+                        column,
+                        message: format!("Expected closing tag for <{}> but found </{}>", node_name, closing_name),
+                    });
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(MarkupElement { node_name, number, attributes, children });
+            }
+            match self.peek() {
+                Some('<') => children.push(self.parse_element()?),
+                Some(_) => return Err(self.error("Text content is not supported")),
+                None => return Err(self.error(&format!("Unclosed element <{}>", node_name))),
+            }
+        }
+    }
+}
+
+/// Prints an element tree as canonical markup: one element per line, indented by four spaces per
+/// level, with attributes sorted by name and childless elements self-closed.
+pub fn print_document(root: &MarkupElement) -> String {
+    let mut output = String::new();
+    print_element(root, 0, &mut output);
+    output
+}
+
+fn print_element(elem: &MarkupElement, depth: usize, output: &mut String) {
+    let indent = "    ".repeat(depth);
+    output.push_str(&indent);
+    output.push('<');
+    output.push_str(&elem.node_name);
+    let mut attributes: Vec<(&String, &String)> = elem.attributes.iter().collect();
+    attributes.sort();
+    for (name, value) in attributes {
+        output.push_str(&format!(" {}=\"{}\"", name, escape_attribute_value(value)));
+    }
+    if elem.children.is_empty() {
+        output.push_str("/>\n");
+    } else {
+        output.push_str(">\n");
+        for child in elem.children.iter() {
+            print_element(child, depth + 1, output);
+        }
+        output.push_str(&format!("{}</{}>\n", indent, elem.node_name));
+    }
+}
+
+fn escape_attribute_value(value: &str) -> String {
+    value.chars().map(|c| match c {
+        '&' => String::from("&amp;"),
+        '<' => String::from("&lt;"),
+        '>' => String::from("&gt;"),
+        '"' => String::from("&quot;"),
+        c => c.to_string(),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_document_and_numbers_elements() {
+        let doc = parse_document(r#"
+            <?xml version="1.0"?>
+            <imuroot version="0.0.0">
+                <!-- header -->
+                <box height="40dp"/>
+                <box layout-direction='column'>
+                    <box width="2*" />
+                </box>
+            </imuroot>"#).unwrap();
+
+        assert_eq!(doc.node_name, "imuroot");
+        assert_eq!(doc.number, 0);
+        assert_eq!(doc.attributes.get("version").map(String::as_str), Some("0.0.0"));
+        assert_eq!(doc.children.len(), 2);
+        assert_eq!(doc.children[0].number, 1);
+        assert_eq!(doc.children[0].attributes.get("height").map(String::as_str), Some("40dp"));
+        assert_eq!(doc.children[1].number, 2);
+        assert_eq!(doc.children[1].children[0].number, 3);
+        assert_eq!(doc.children[1].children[0].attributes.get("width").map(String::as_str), Some("2*"));
+    }
+
+    #[test]
+    fn decodes_entities_in_attribute_values() {
+        let doc = parse_document(r#"<box label="a &lt;b&gt; &amp; &quot;c&quot;"/>"#).unwrap();
+        assert_eq!(doc.attributes.get("label").map(String::as_str), Some(r#"a <b> & "c""#));
+    }
+
+    #[test]
+    fn reports_line_and_column_of_errors() {
+        let err = parse_document("<imuroot>\n  <box>\n</imuroot>").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 1));
+        assert_eq!(err.message, "Expected closing tag for <box> but found </imuroot>");
+
+        let err = parse_document("<imuroot>\n  <box height=40dp/>\n</imuroot>").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 15));
+
+        let err = parse_document("<box a=\"1\" a=\"2\"/>").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 12));
+
+        assert!(parse_document("").is_err());
+        assert!(parse_document("<box/><box/>").is_err());
+        assert!(parse_document("<box>text</box>").is_err());
+    }
+
+    #[test]
+    fn prints_canonical_markup() {
+        let doc = parse_document(r#"<imuroot version="0.0.0"><box width="20dp" height="40dp" label="a&amp;b"/><box><box/></box></imuroot>"#).unwrap();
+        assert_eq!(print_document(&doc), r#"<imuroot version="0.0.0">
+    <box height="40dp" label="a&amp;b" width="20dp"/>
+    <box>
+        <box/>
+    </box>
+</imuroot>
+"#);
+    }
+
+    /// Builds a pseudo-random tree numbered in document order, as the parser would number it
+    fn random_tree(seed: &mut u64, depth: usize, next_number: &mut i32) -> MarkupElement {
+        let mut next_random = |bound: u64| {
+            *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (*seed >> 33) % bound
+        };
+        let names = ["box", "imuroot", "text-box", "ns:item"];
+        let values = ["40dp", "2*", "content", "fill", "a \"quoted\" <value> & more", "", "it's"];
+        let node_name = String::from(names[next_random(names.len() as u64) as usize]);
+        let attributes = (0..next_random(4))
+            .map(|i| (format!("attr-{}", i), String::from(values[next_random(values.len() as u64) as usize])))
+            .collect();
+        let child_count = if depth == 0 { 0 } else { next_random(4) };
+        let number = *next_number;
+        *next_number += 1;
+        let children = (0..child_count).map(|_| random_tree(seed, depth - 1, next_number)).collect();
+        MarkupElement { node_name, number, attributes, children }
+    }
+
+    #[test]
+    fn print_and_parse_round_trip() {
+        for seed in 0..200 {
+            let mut seed = seed;
+            let tree = random_tree(&mut seed, 4, &mut 0);
+            let printed = print_document(&tree);
+            let reparsed = parse_document(&printed).unwrap();
+            assert_eq!(reparsed, tree);
+            assert_eq!(print_document(&reparsed), printed);
+        }
+    }
+
+    #[test]
+    fn parses_lengths() {
+        assert_eq!(parse_length("40dp").ok(), Some(MarkupLength::Dp(40.0)));
+        assert_eq!(parse_length("12.5dp").ok(), Some(MarkupLength::Dp(12.5)));
+        assert_eq!(parse_length("2*").ok(), Some(MarkupLength::Star(2.0)));
+        assert_eq!(parse_length("1.5*").ok(), Some(MarkupLength::Star(1.5)));
+        assert_eq!(parse_length(".5*").ok(), Some(MarkupLength::Star(0.5)));
+        assert_eq!(parse_length("25%").ok(), Some(MarkupLength::Percent(25.0)));
+        assert_eq!(parse_length("12.5 %").ok(), Some(MarkupLength::Percent(12.5)));
+        assert_eq!(parse_length(" 40dp ").ok(), Some(MarkupLength::Dp(40.0)));
+        assert_eq!(parse_length("40 dp").ok(), Some(MarkupLength::Dp(40.0)));
+        assert_eq!(parse_length("-0dp").ok(), Some(MarkupLength::Dp(0.0)));
+        assert_eq!(parse_length(" content").ok(), Some(MarkupLength::Content));
+        assert_eq!(parse_length("fill ").ok(), Some(MarkupLength::Fill));
+    }
+
+    #[test]
+    fn distinguishes_missing_malformed_and_negative_lengths() {
+        let doc = parse_document(r#"<box width="-10dp" height="1.2.3*" min-width="40px"/>"#).unwrap();
+        assert_eq!(doc.get_length("max-width"), Ok(None));
+        assert_eq!(doc.get_width(), Err(LayoutError::NegativeLength {
+            number: 0,
+            node_name: String::from("box"),
+            attribute: String::from("width"),
+            value: String::from("-10dp"),
+        }));
+        assert_eq!(doc.get_height(), Err(LayoutError::BadLength {
+            number: 0,
+            node_name: String::from("box"),
+            attribute: String::from("height"),
+            value: String::from("1.2.3*"),
+        }));
+        assert!(doc.get_length("min-width").is_err());
+        for malformed in ["", "dp", "*", "40", "4 0dp", "40DP", "1e3dp", "NaNdp"].iter() {
+            assert!(parse_length(malformed).is_err(), "'{}' should be malformed", malformed);
         }
+        assert!(matches!(parse_length(&format!("{}dp", "9".repeat(60))), Err(LengthParseError::Malformed)));
     }
 }
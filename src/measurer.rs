@@ -1,95 +1,370 @@
-use crate::layout::LayoutDirection;
+use crate::grid;
+use crate::grid::Grid;
+use crate::layout::{
+    Alignment, Edges, GridPlacement, Justification, LayoutDirection, LayoutError, Offsets, Overflow, Position,
+    Visibility,
+};
 use crate::markup::{MarkupElement, MarkupLength};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
-pub fn measure_element_tree(elem: &MarkupElement) -> MeasuredElement {
-    let children: Vec<MeasuredElement> = elem.children.iter().map(measure_element_tree).collect();
-    let layout_direction = elem.get_layout_direction();
-
-    let primary_measurement = match elem.get_primary_measure(&layout_direction) {
-        Some(MarkupLength::Content) => {
-            let total_primary_content_measure: f32 = children.iter()
-                .map(|child| child.primary_measure(&layout_direction).dp_or_default(0.0))
-                .sum();
-            MeasuredLength::Dp(total_primary_content_measure)
-        },
-        Some(MarkupLength::Dp(dp)) => MeasuredLength::Dp(dp),
-        Some(MarkupLength::Star(star)) => MeasuredLength::Star(star),
-        Some(MarkupLength::Fill) => panic!("Fill not valid as a primary measure"),
-        None => MeasuredLength::Star(1.0)
+/// Measures an element and its descendants. Which of the element's lengths are primary and secondary measures depends
+/// on its parent's layout direction, since that's the axis its parent arranges it along.
+pub fn measure_element_tree(elem: &MarkupElement, parent_direction: &LayoutDirection) -> Result<MeasuredElement, LayoutError> {
+    measure_element(elem, Some(parent_direction))
+}
+
+/// Measures the root element, which has no parent axes: star, fill and unspecified lengths all measure as Fill since
+/// the root fills the viewport along both axes.
+pub fn measure_root(root_elem: &MarkupElement) -> Result<MeasuredElement, LayoutError> {
+    measure_element(root_elem, None)
+}
+
+fn measure_element(elem: &MarkupElement, parent_direction: Option<&LayoutDirection>) -> Result<MeasuredElement, LayoutError> {
+    let layout_direction = elem.get_layout_direction()?;
+    let mut children = vec![];
+    for child in elem.children.iter() {
+        if child.get_visibility()? != Visibility::Collapsed {
+            children.push(measure_element_tree(child, &layout_direction)?);
+        }
+    }
+    let padding = elem.get_padding()?;
+    let gap = elem.get_gap()?;
+    let flow_children: Vec<&MeasuredElement> = children.iter().filter(|child| !child.is_absolute()).collect();
+    let total_gap = gap * flow_children.len().saturating_sub(1) as f32;
+
+    let total_primary_content_measure: f32 = flow_children.iter()
+        .map(|child| child.intrinsic_primary_size(&layout_direction) + child.margin.primary(&layout_direction))
+        .sum::<f32>() + total_gap + padding.primary(&layout_direction);
+    let max_child_secondary_measure: f32 = max_content(&flow_children, |child| {
+        child.intrinsic_secondary_size(&layout_direction) + child.margin.secondary(&layout_direction)
+    }) + padding.secondary(&layout_direction);
+    let grid = if layout_direction.is_grid() { Some(grid::measure_grid(elem, &children)?) } else { None };
+    let (content_width, content_height) = if let Some(grid) = &grid {
+        (grid.content_width(gap) + padding.horizontal(), grid.content_height(gap) + padding.vertical())
+    } else if layout_direction.is_stack() {
+        (max_content(&flow_children, |child| child.intrinsic_width() + child.margin.horizontal()) + padding.horizontal(),
+         max_content(&flow_children, |child| child.intrinsic_height() + child.margin.vertical()) + padding.vertical())
+    } else if layout_direction.is_row() {
+        (max_child_secondary_measure, total_primary_content_measure)
+    } else {
+        (total_primary_content_measure, max_child_secondary_measure)
     };
-    let secondary_measurement = match elem.get_secondary_measure(&layout_direction) {
-        Some(MarkupLength::Content) => {
-            let max_child_secondary_measure: f32 = children.iter()
-                .map(|child| child.secondary_measure(&layout_direction).dp_or_default(0.0))
-                .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal)).unwrap_or(0.0);
-            MeasuredLength::Dp(max_child_secondary_measure)
-        },
-        Some(MarkupLength::Dp(dp)) => MeasuredLength::Dp(dp),
-        Some(MarkupLength::Star(_)) => panic!("Star not valid as a secondary measure"),
-        Some(MarkupLength::Fill) => MeasuredLength::Fill,
-        None => MeasuredLength::Fill,
+
+    let constraints = measure_constraints(elem)?;
+    let position = elem.get_position()?;
+    let is_primary = |attribute| {
+        parent_direction.map(|direction| position == Position::Static && direction.is_primary(attribute))
     };
-    MeasuredElement {
+    let content_sized_primary = elem.get_length(layout_direction.primary_attribute())? == Some(MarkupLength::Content);
+    let width = measure_length(elem, "width", is_primary("width"), content_width)?;
+    let height = measure_length(elem, "height", is_primary("height"), content_height)?;
+    Ok(MeasuredElement {
         number: elem.number,
-        width: match layout_direction {
-            LayoutDirection::Row => secondary_measurement,
-            LayoutDirection::Column => primary_measurement
-        },
-        height: match layout_direction {
-            LayoutDirection::Row => primary_measurement,
-            LayoutDirection::Column => secondary_measurement
-        },
+        node_name: elem.node_name.clone(),
+        width: SizeConstraints::clamp_dp(width, &constraints.min_width, &constraints.max_width),
+        height: SizeConstraints::clamp_dp(height, &constraints.min_height, &constraints.max_height),
+        content_width,
+        content_height,
+        content_sized_primary,
+        constraints,
+        padding,
+        margin: elem.get_margin()?,
+        position,
+        offsets: elem.get_offsets()?,
+        aspect_ratio: elem.get_aspect_ratio()?,
+        grid_placement: elem.get_grid_placement()?,
+        visibility: elem.get_visibility()?,
+        gap,
+        line_gap: elem.get_line_gap()?,
+        align: elem.get_align()?,
+        align_self: elem.get_align_self()?,
+        justify: elem.get_justify()?,
+        overflow: elem.get_overflow()?,
+        scroll_offset: 0.0,
         layout_direction,
+        grid,
         children,
+    })
+}
+
+/// The largest size any of the children needs, or 0 without children
+fn max_content(children: &[&MeasuredElement], size: impl Fn(&MeasuredElement) -> f32) -> f32 {
+    children.iter().map(|child| size(child)).max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal)).unwrap_or(0.0)
+}
+
+/// Measures a length that's a primary measure of the parent's layout, a secondary one, or `None` for the root
+fn measure_length(elem: &MarkupElement, attribute: &str, is_primary: Option<bool>,
+                  content_size: f32) -> Result<MeasuredLength, LayoutError> {
+    match (elem.get_length(attribute)?, is_primary) {
+        (Some(MarkupLength::Content), _) => Ok(MeasuredLength::Dp(content_size)),
+        (Some(MarkupLength::Dp(dp)), _) => Ok(MeasuredLength::Dp(dp)),
+        (Some(MarkupLength::Percent(percent)), _) => Ok(MeasuredLength::Percent(percent)),
+        (Some(MarkupLength::Star(star)), Some(true)) => Ok(MeasuredLength::Star(star)),
+        (None, Some(true)) => Ok(MeasuredLength::Star(1.0)),
+        (Some(MarkupLength::Fill), Some(true)) => Err(LayoutError::FillOnPrimaryMeasure {
+            number: elem.number,
+            node_name: elem.node_name.clone(),
+            attribute: String::from(attribute),
+        }),
+        (Some(MarkupLength::Star(_)), Some(false)) => Err(LayoutError::StarOnSecondaryMeasure {
+            number: elem.number,
+            node_name: elem.node_name.clone(),
+            attribute: String::from(attribute),
+        }),
+        (Some(MarkupLength::Fill), _) | (Some(MarkupLength::Star(_)), None) | (None, _) => Ok(MeasuredLength::Fill),
     }
 }
 
+fn measure_constraints(elem: &MarkupElement) -> Result<SizeConstraints, LayoutError> {
+    let measure_constraint = |attribute: &str| match elem.get_length(attribute)? {
+        Some(MarkupLength::Dp(dp)) => Ok(Some(MeasuredLength::Dp(dp))),
+        Some(MarkupLength::Percent(percent)) => Ok(Some(MeasuredLength::Percent(percent))),
+        Some(_) => Err(LayoutError::BadLength {
+            number: elem.number,
+            node_name: elem.node_name.clone(),
+            attribute: String::from(attribute),
+            value: elem.attributes[attribute].clone(),
+        }),
+        None => Ok(None),
+    };
+    Ok(SizeConstraints {
+        min_width: measure_constraint("min-width")?,
+        max_width: measure_constraint("max-width")?,
+        min_height: measure_constraint("min-height")?,
+        max_height: measure_constraint("max-height")?,
+    })
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MeasuredLength {
     Dp(f32),
     Star(f32),
+    /// Percentage of the parent's size along the same axis, resolved by the arranger
+    Percent(f32),
     Fill
 }
 
 impl MeasuredLength {
-    pub(crate) fn apply_star_value(&self, star_unit_value: f32) -> f32 {
+    pub(crate) fn resolve_percent(&self, parent_size: f32) -> MeasuredLength {
         match self {
-            MeasuredLength::Dp(dp) => *dp,
-            MeasuredLength::Star(star) => *star * star_unit_value,
-            MeasuredLength::Fill => panic!("Fill lengths can't have star values applied")
+            MeasuredLength::Percent(percent) => MeasuredLength::Dp(parent_size * percent / 100.0),
+            _ => *self,
         }
     }
 
-    fn dp_or_default(&self, default: f32) -> f32 {
+    /// Resolves a primary measure to dp, or `None` for Fill which has no primary size and Percent which must be
+    /// resolved against the parent first
+    pub(crate) fn apply_star_value(&self, star_unit_value: f32) -> Option<f32> {
         match self {
-            MeasuredLength::Dp(dp) => *dp,
+            MeasuredLength::Dp(dp) => Some(*dp),
+            MeasuredLength::Star(star) => Some(*star * star_unit_value),
+            MeasuredLength::Percent(_) | MeasuredLength::Fill => None
+        }
+    }
+}
+
+/// Min and max sizes, each either Dp or Percent
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct SizeConstraints {
+    pub(crate) min_width: Option<MeasuredLength>,
+    pub(crate) max_width: Option<MeasuredLength>,
+    pub(crate) min_height: Option<MeasuredLength>,
+    pub(crate) max_height: Option<MeasuredLength>,
+}
+
+impl SizeConstraints {
+    /// Returns the (min, max) limits along the parent's primary axis, resolving percentages against its size
+    pub(crate) fn primary_limits(&self, layout_direction: &LayoutDirection, parent_size: f32) -> (f32, f32) {
+        if layout_direction.is_row() { self.height_limits(parent_size) } else { self.width_limits(parent_size) }
+    }
+
+    /// Returns the (min, max) limits along the parent's secondary axis, resolving percentages against its size
+    pub(crate) fn secondary_limits(&self, layout_direction: &LayoutDirection, parent_size: f32) -> (f32, f32) {
+        if layout_direction.is_row() { self.width_limits(parent_size) } else { self.height_limits(parent_size) }
+    }
+
+    pub(crate) fn width_limits(&self, parent_width: f32) -> (f32, f32) {
+        SizeConstraints::limits(&self.min_width, &self.max_width, parent_width)
+    }
+
+    pub(crate) fn height_limits(&self, parent_height: f32) -> (f32, f32) {
+        SizeConstraints::limits(&self.min_height, &self.max_height, parent_height)
+    }
+
+    fn limits(min: &Option<MeasuredLength>, max: &Option<MeasuredLength>, parent_size: f32) -> (f32, f32) {
+        let resolve = |limit: &Option<MeasuredLength>, default: f32| match limit.map(|l| l.resolve_percent(parent_size)) {
+            Some(MeasuredLength::Dp(dp)) => dp,
             _ => default,
+        };
+        (resolve(min, 0.0), resolve(max, f32::INFINITY))
+    }
+
+    /// Clamps Dp measures by any Dp limits so content-sized parents see the constrained size
+    fn clamp_dp(measure: MeasuredLength, min: &Option<MeasuredLength>, max: &Option<MeasuredLength>) -> MeasuredLength {
+        match measure {
+            MeasuredLength::Dp(dp) => {
+                let (min, max) = SizeConstraints::dp_limits(min, max);
+                MeasuredLength::Dp(clamp(dp, min, max))
+            }
+            _ => measure
         }
     }
+
+    /// Returns the (min, max) limits ignoring percentages, for use before the parent's size is known
+    fn dp_limits(min: &Option<MeasuredLength>, max: &Option<MeasuredLength>) -> (f32, f32) {
+        let max = match max { Some(MeasuredLength::Dp(max)) => *max, _ => f32::INFINITY };
+        let min = match min { Some(MeasuredLength::Dp(min)) => *min, _ => 0.0 };
+        (min, max)
+    }
+}
+
+/// Clamps a size between limits, with the min taking precedence if they conflict
+pub(crate) fn clamp(size: f32, min: f32, max: f32) -> f32 {
+    size.min(max).max(min)
 }
 
 #[derive(PartialEq, Debug)]
 pub struct MeasuredElement {
     pub(crate) number: i32,
+    pub(crate) node_name: String,
     pub(crate) width: MeasuredLength,
     pub(crate) height: MeasuredLength,
+    /// Size of the element's children, gaps and padding, which `content` lengths measure as
+    pub(crate) content_width: f32,
+    pub(crate) content_height: f32,
+    /// Whether the size along the element's own primary axis is measured from its content, in which case star
+    /// children keep at least their intrinsic size
+    pub(crate) content_sized_primary: bool,
+    pub(crate) constraints: SizeConstraints,
+    pub(crate) padding: Edges,
+    pub(crate) margin: Edges,
+    pub(crate) position: Position,
+    pub(crate) offsets: Offsets,
+    /// Width divided by height, which sizes one axis from the other
+    pub(crate) aspect_ratio: Option<f32>,
+    /// Where the element goes when its parent is a grid
+    pub(crate) grid_placement: GridPlacement,
+    pub(crate) visibility: Visibility,
+    /// Spacing between consecutive children along the primary axis
+    pub(crate) gap: f32,
+    /// Spacing between lines of a wrapping container along the secondary axis
+    pub(crate) line_gap: f32,
+    /// Default alignment of children along the secondary axis
+    pub(crate) align: Alignment,
+    /// Overrides the parent's `align` for this element
+    pub(crate) align_self: Option<Alignment>,
+    /// Distribution of leftover space between children along the primary axis
+    pub(crate) justify: Justification,
+    pub(crate) overflow: Overflow,
+    /// How far a scroll container is scrolled, as requested in the layout options
+    pub(crate) scroll_offset: f32,
     pub(crate) layout_direction: LayoutDirection,
+    /// Tracks and child areas of a grid element
+    pub(crate) grid: Option<Grid>,
     pub(crate) children: Vec<MeasuredElement>,
 }
 
 impl MeasuredElement {
-    pub fn primary_measure(&self, layout_direction: &LayoutDirection) -> MeasuredLength {
-        match layout_direction {
-            LayoutDirection::Row => self.height,
-            LayoutDirection::Column => self.width,
+    /// Sets the scroll offset of each scroll container in the tree that has one in `scroll_offsets`
+    pub(crate) fn apply_scroll_offsets(&mut self, scroll_offsets: &HashMap<i32, f32>) {
+        if self.overflow == Overflow::Scroll {
+            self.scroll_offset = scroll_offsets.get(&self.number).copied().unwrap_or(0.0);
         }
+        for child in self.children.iter_mut() {
+            child.apply_scroll_offsets(scroll_offsets);
+        }
+    }
+
+    /// Whether this element is left out of its parent's layout
+    pub(crate) fn is_absolute(&self) -> bool {
+        self.position == Position::Absolute
+    }
+
+    pub fn primary_measure(&self, layout_direction: &LayoutDirection) -> MeasuredLength {
+        if layout_direction.is_row() { self.height } else { self.width }
     }
 
     pub fn secondary_measure(&self, layout_direction: &LayoutDirection) -> MeasuredLength {
-        match layout_direction {
-            LayoutDirection::Row => self.width,
-            LayoutDirection::Column => self.height,
+        if layout_direction.is_row() { self.width } else { self.height }
+    }
+
+    /// With an aspect ratio, whether it derives the width from the height rather than the height from the width. The
+    /// height is derived unless only the height is fixed.
+    pub(crate) fn derives_width(&self) -> bool {
+        let is_fixed = |length| matches!(length, MeasuredLength::Dp(_) | MeasuredLength::Percent(_));
+        is_fixed(self.height) && !is_fixed(self.width)
+    }
+
+    /// Whether the aspect ratio derives this element's size along its parent's primary axis
+    pub(crate) fn derives_primary(&self, layout_direction: &LayoutDirection) -> bool {
+        self.aspect_ratio.is_some() && self.derives_width() != layout_direction.is_row()
+    }
+
+    pub(crate) fn derives_secondary(&self, layout_direction: &LayoutDirection) -> bool {
+        self.aspect_ratio.is_some() && self.derives_width() == layout_direction.is_row()
+    }
+
+    /// Applies the aspect ratio to the size of the axis it isn't derived along
+    pub(crate) fn derive_size(&self, size: f32) -> f32 {
+        match self.aspect_ratio {
+            Some(ratio) if self.derives_width() => size * ratio,
+            Some(ratio) => size / ratio,
+            None => size,
+        }
+    }
+
+    /// The width this element needs: its dp width, or for star, fill and percent widths its content width kept within
+    /// its dp min and max widths. A width derived from a dp height by the aspect ratio is used as is.
+    pub(crate) fn intrinsic_width(&self) -> f32 {
+        match (self.width, self.height) {
+            (_, MeasuredLength::Dp(height)) if self.aspect_ratio.is_some() && self.derives_width() => {
+                self.derive_size(height)
+            }
+            (MeasuredLength::Dp(dp), _) => dp,
+            _ => {
+                let (min, max) = SizeConstraints::dp_limits(&self.constraints.min_width, &self.constraints.max_width);
+                clamp(self.content_width, min, max)
+            }
+        }
+    }
+
+    /// The height this element needs: its dp height, or for star, fill and percent heights its content height kept
+    /// within its dp min and max heights. A height derived from a dp width by the aspect ratio is used as is.
+    pub(crate) fn intrinsic_height(&self) -> f32 {
+        match (self.height, self.width) {
+            (_, MeasuredLength::Dp(width)) if self.aspect_ratio.is_some() && !self.derives_width() => {
+                self.derive_size(width)
+            }
+            (MeasuredLength::Dp(dp), _) => dp,
+            _ => {
+                let (min, max) = SizeConstraints::dp_limits(&self.constraints.min_height, &self.constraints.max_height);
+                clamp(self.content_height, min, max)
+            }
+        }
+    }
+
+    pub(crate) fn intrinsic_primary_size(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.intrinsic_height() } else { self.intrinsic_width() }
+    }
+
+    pub(crate) fn intrinsic_secondary_size(&self, layout_direction: &LayoutDirection) -> f32 {
+        if layout_direction.is_row() { self.intrinsic_width() } else { self.intrinsic_height() }
+    }
+
+    /// Returns the (min, max) limits along the parent's primary axis. Without an explicit min, star children of a
+    /// parent sized to its content can't shrink below their intrinsic size, which leaves each room for its own content.
+    pub(crate) fn primary_limits(&self, layout_direction: &LayoutDirection, parent_size: f32,
+                                 content_sized_parent: bool) -> (f32, f32) {
+        let (min, max) = self.constraints.primary_limits(layout_direction, parent_size);
+        let has_min = if layout_direction.is_row() {
+            self.constraints.min_height.is_some()
+        } else {
+            self.constraints.min_width.is_some()
+        };
+        match self.primary_measure(layout_direction) {
+            MeasuredLength::Star(_) if content_sized_parent && !has_min => (self.intrinsic_primary_size(layout_direction).min(max), max),
+            _ => (min, max),
         }
     }
 }
@@ -1,4 +1,6 @@
 use hex;
+use std::sync::OnceLock;
+
 use regex::Regex;
 use skia_safe::{canvas::Canvas, Paint, Rect as SkRect};
 
@@ -14,7 +16,8 @@ pub struct ArgbColour {
 }
 impl ArgbColour {
     pub fn from_hex(hex: &str) -> Result<ArgbColour, ColourParseError> {
-        let re = Regex::new(r"#?([\dA-Za-z]{8}|[\dA-Za-z]{6}|[\dA-Za-z]{3})").unwrap();
+        static HEX_RE: OnceLock<Regex> = OnceLock::new();
+        let re = HEX_RE.get_or_init(|| Regex::new(r"#?([\dA-Za-z]{8}|[\dA-Za-z]{6}|[\dA-Za-z]{3})").unwrap());
         let captures = re.captures(hex);
         let digits = captures.and_then(|c| { c.get(1) }).map(|m| m.as_str());
         match digits {
@@ -54,7 +57,7 @@ impl ArgbColour {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -80,14 +83,53 @@ impl Rect {
     }
 }
 
+/// A step in drawing a laid out document, in paint order
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DrawCommand {
+    Rect(Rect),
+    /// Clips everything drawn up to the matching `PopClip` to a rect, within any clip already pushed
+    PushClip { x: f32, y: f32, width: f32, height: f32 },
+    PopClip,
+}
+
+impl DrawCommand {
+    pub fn scale(&self, scale_factor: f32) -> DrawCommand {
+        match self {
+            DrawCommand::Rect(rect) => DrawCommand::Rect(rect.scale(scale_factor)),
+            DrawCommand::PushClip { x, y, width, height } => DrawCommand::PushClip {
+                x: x * scale_factor,
+                y: y * scale_factor,
+                width: width * scale_factor,
+                height: height * scale_factor,
+            },
+            DrawCommand::PopClip => DrawCommand::PopClip,
+        }
+    }
+}
+
 pub fn draw_ui(canvas: &mut Canvas, rects: Vec<Rect>) {
+    draw_display_list(canvas, rects.into_iter().map(DrawCommand::Rect).collect());
+}
+
+pub fn draw_display_list(canvas: &mut Canvas, commands: Vec<DrawCommand>) {
     let mut paint = Paint::default();
     paint.set_anti_alias(true);
     paint.set_stroke_width(1.0);
-    for rect in &rects {
-        let colour = rect.fill_colour;
-        paint.set_argb(colour.a, colour.r, colour.g, colour.b);
-        canvas.draw_rect(rect.to_skia_rect(), &paint);
+    for command in &commands {
+        match command {
+            DrawCommand::Rect(rect) => {
+                let colour = rect.fill_colour;
+                paint.set_argb(colour.a, colour.r, colour.g, colour.b);
+                canvas.draw_rect(rect.to_skia_rect(), &paint);
+            }
+            DrawCommand::PushClip { x, y, width, height } => {
+                canvas.save();
+                canvas.clip_rect(SkRect::from_xywh(*x, *y, *width, *height), None, true);
+            }
+            DrawCommand::PopClip => {
+                canvas.restore();
+            }
+        }
     }
 }
 
@@ -0,0 +1,197 @@
+use std::fmt;
+
+use crate::layout::{LayoutDirection, LayoutError, Position, Visibility};
+use crate::markup::{MarkupElement, MarkupLength};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub number: i32,
+    /// Slash-separated node names from the root, with each child's index among its siblings, e.g. `imuroot/box[1]`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// Checks a document against the layout rules, reporting every violation rather than stopping at the first.
+/// A document with no `Severity::Error` diagnostics will lay out without a `LayoutError`.
+pub fn validate(root: &MarkupElement) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    validate_element(root, &root.node_name, None, &mut diagnostics);
+    diagnostics
+}
+
+/// Validates an element whose parent has the given layout direction, or `None` for the root, which has no parent
+/// axes, and children of an element with an invalid direction
+fn validate_element(elem: &MarkupElement, path: &str, parent_direction: Option<&LayoutDirection>,
+                    diagnostics: &mut Vec<Diagnostic>) {
+    let layout_direction = check(elem.get_layout_direction(), elem, path, diagnostics);
+    let width = check(elem.get_width(), elem, path, diagnostics).flatten();
+    let height = check(elem.get_height(), elem, path, diagnostics).flatten();
+    check(elem.get_padding(), elem, path, diagnostics);
+    check(elem.get_margin(), elem, path, diagnostics);
+    check(elem.get_gap(), elem, path, diagnostics);
+    check(elem.get_line_gap(), elem, path, diagnostics);
+    check(elem.get_align(), elem, path, diagnostics);
+    check(elem.get_align_self(), elem, path, diagnostics);
+    check(elem.get_justify(), elem, path, diagnostics);
+    let columns = check(elem.get_columns(), elem, path, diagnostics);
+    check(elem.get_rows(), elem, path, diagnostics);
+    check(elem.get_grid_placement(), elem, path, diagnostics);
+    let position = check(elem.get_position(), elem, path, diagnostics);
+    check(elem.get_offsets(), elem, path, diagnostics);
+    check(elem.get_aspect_ratio(), elem, path, diagnostics);
+    check(elem.get_visibility(), elem, path, diagnostics);
+    check(elem.get_overflow(), elem, path, diagnostics);
+
+    for attribute in ["min-width", "max-width", "min-height", "max-height"].iter() {
+        match check(elem.get_length(attribute), elem, path, diagnostics).flatten() {
+            Some(MarkupLength::Dp(_)) | Some(MarkupLength::Percent(_)) | None => (),
+            Some(_) => diagnostics.push(error(elem, path, LayoutError::BadLength {
+                number: elem.number,
+                node_name: elem.node_name.clone(),
+                attribute: String::from(*attribute),
+                value: elem.attributes[*attribute].clone(),
+            })),
+        }
+    }
+
+    if let Some(parent_direction) = parent_direction {
+        for (attribute, length) in [("width", &width), ("height", &height)].iter() {
+            let is_primary = position != Some(Position::Absolute) && parent_direction.is_primary(attribute);
+            let err = match (length, is_primary) {
+                (Some(MarkupLength::Fill), true) => Some(LayoutError::FillOnPrimaryMeasure {
+                    number: elem.number,
+                    node_name: elem.node_name.clone(),
+                    attribute: String::from(*attribute),
+                }),
+                (Some(MarkupLength::Star(_)), false) => Some(LayoutError::StarOnSecondaryMeasure {
+                    number: elem.number,
+                    node_name: elem.node_name.clone(),
+                    attribute: String::from(*attribute),
+                }),
+                _ => None,
+            };
+            diagnostics.extend(err.map(|err| error(elem, path, err)));
+        }
+    }
+
+    for (attribute, length) in [("width", &width), ("height", &height)].iter() {
+        if let (Some(MarkupLength::Content), true) = (length, elem.children.is_empty()) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                number: elem.number,
+                path: String::from(path),
+                message: format!("Content {} on a leaf node always measures 0dp", attribute),
+            });
+        }
+    }
+
+    // Without columns a grid has a single one
+    let column_count = match (&layout_direction, &columns) {
+        (Some(direction), Some(columns)) if direction.is_grid() => Some(columns.len().max(1)),
+        _ => None,
+    };
+    for (index, child) in elem.children.iter().enumerate() {
+        let child_path = format!("{}/{}[{}]", path, child.node_name, index);
+        if let (Some(column_count), Ok(placement)) = (column_count, child.get_grid_placement()) {
+            let is_placed = child.get_position() == Ok(Position::Static)
+                && child.get_visibility() != Ok(Visibility::Collapsed);
+            if is_placed && placement.column.is_some_and(|column| column >= column_count) {
+                diagnostics.push(error(child, &child_path, child.bad_attribute_value("grid-column")));
+            }
+        }
+        validate_element(child, &child_path, layout_direction.as_ref(), diagnostics);
+    }
+}
+
+/// Records a `LayoutError` as an error diagnostic, returning the value if there wasn't one
+fn check<T>(result: Result<T, LayoutError>, elem: &MarkupElement, path: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<T> {
+    result.map_err(|err| diagnostics.push(error(elem, path, err))).ok()
+}
+
+fn error(elem: &MarkupElement, path: &str, err: LayoutError) -> Diagnostic {
+    Diagnostic {
+        severity: Severity::Error,
+        number: elem.number,
+        path: String::from(path),
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::markup::parse_document;
+
+    use super::*;
+
+    #[test]
+    fn valid_document_has_no_diagnostics() {
+        let doc = parse_document(r#"
+            <imuroot version="0.0.0">
+                <box height="40dp"/>
+                <box height="content"><box height="20dp"/></box>
+                <box height="2*" width="fill"><box width="30dp"/></box>
+            </imuroot>"#).unwrap();
+        assert_eq!(validate(&doc), vec![]);
+    }
+
+    #[test]
+    fn reports_every_violation_with_path_and_severity() {
+        let doc = parse_document(r#"
+            <imuroot layout-direction="column" width="100dp" height="fill">
+                <box height="content">
+                    <box height="fill"/>
+                    <box><box height="2*"/></box>
+                </box>
+                <box width="content" layout-direction="sideways"/>
+                <box width="lots" height="-1dp" max-height="2*"/>
+                <box height="2*" padding-left="fill"/>
+            </imuroot>"#).unwrap();
+        let diagnostics = validate(&doc);
+        let summary: Vec<(Severity, i32, &str)> = diagnostics.iter()
+            .map(|d| (d.severity, d.number, &d.path[..]))
+            .collect();
+        assert_eq!(summary, vec![
+            (Severity::Error, 2, "imuroot/box[0]/box[0]"),
+            (Severity::Error, 5, "imuroot/box[1]"),
+            (Severity::Warning, 5, "imuroot/box[1]"),
+            (Severity::Error, 6, "imuroot/box[2]"),
+            (Severity::Error, 6, "imuroot/box[2]"),
+            (Severity::Error, 6, "imuroot/box[2]"),
+            (Severity::Error, 7, "imuroot/box[3]"),
+            (Severity::Error, 7, "imuroot/box[3]"),
+        ]);
+        assert_eq!(diagnostics[0].message, "Fill not valid as a primary measure (height on <box> #2)");
+        assert_eq!(diagnostics[1].message, "Bad layout-direction 'sideways' on <box> #5");
+    }
+
+    #[test]
+    fn reports_grid_columns_past_the_last_track() {
+        let doc = parse_document(r#"
+            <imuroot layout-direction="grid" columns="1* 1*">
+                <box grid-column="1"/>
+                <box grid-column="2"/>
+                <box grid-column="2" position="absolute"/>
+            </imuroot>"#).unwrap();
+        let diagnostics = validate(&doc);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, "imuroot/box[1]");
+        assert_eq!(diagnostics[0].message, "Bad grid-column '2' on <box> #2");
+    }
+}